@@ -0,0 +1,36 @@
+use imgui::{Context, FontConfig, FontSource};
+
+/// Default font rasterized for the given DPI scale, so text stays crisp on high DPI monitors.
+#[derive(Debug, Clone)]
+pub struct FontSetup {
+    /// Font size in logical pixels.
+    pub base_size: f32,
+    pub oversample_h: i32,
+    pub pixel_snap_h: bool,
+}
+
+impl FontSetup {
+    pub fn new(base_size: f32) -> Self {
+        Self {
+            base_size,
+            oversample_h: 1,
+            pixel_snap_h: true,
+        }
+    }
+    /// Replaces atlas content, glyphs are rasterized at `base_size * scale` and scaled back with `font_global_scale`.
+    ///
+    /// Renderer has to re-upload font texture after that.
+    pub fn build(&self, imgui: &mut Context, scale: f64) {
+        imgui.io_mut().font_global_scale = (1.0 / scale) as f32;
+        let mut fonts = imgui.fonts();
+        fonts.clear();
+        fonts.add_font(&[FontSource::DefaultFontData {
+            config: Some(FontConfig {
+                oversample_h: self.oversample_h,
+                pixel_snap_h: self.pixel_snap_h,
+                size_pixels: (self.base_size as f64 * scale) as f32,
+                ..Default::default()
+            }),
+        }]);
+    }
+}
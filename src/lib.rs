@@ -1,17 +1,35 @@
+mod fonts;
 mod platform;
 use bitflags::bitflags;
 use imgui::sys as imgui_sys;
-use std::ops::{Deref, DerefMut};
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+};
 use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::WindowEvent,
     event_loop::EventLoopWindowTarget,
     window::{Window, WindowBuilder, WindowId},
 };
 
-pub use platform::Platform;
+pub use fonts::FontSetup;
+#[cfg(feature = "clipboard")]
+pub use platform::register_clipboard;
+pub use platform::{
+    check_data_layout, enable_docking_and_viewports, focus_order, focused_viewport_keys,
+    handle_character, handle_cursor_moved, handle_keyboard, handle_mouse_wheel, platform_io,
+    platform_io_ref, set_mouse_button, unregister_platform, viewports_enabled, DroppedFile,
+    FocusOrder, Key, LayoutMismatch, Platform, ProxyError, TopLevelFocusOrder,
+};
 
 #[cfg(feature = "wgpu-renderer")]
 pub mod wgpu;
 
+/// imgui allows one context at a time, tests creating one take this lock.
+#[cfg(test)]
+pub(crate) static IMGUI_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
 // reexport git-based forks
 pub mod dependencies {
     pub use imgui;
@@ -21,8 +39,24 @@ pub mod dependencies {
 
 pub trait Viewport {
     type Renderer;
-    fn window(&self) -> &Window;
+    /// `None` for viewports without native window (e.g. offscreen), platform requests to them
+    /// only update cached geometry.
+    fn window(&self) -> Option<&Window>;
+    /// Native handle of the window, for passing it to other GPU or OS APIs.
+    fn raw_window_handle(&self) -> Option<raw_window_handle::RawWindowHandle> {
+        use raw_window_handle::HasRawWindowHandle;
+        self.window().map(|window| window.raw_window_handle())
+    }
     fn on_resize(&mut self);
+    fn scale_factor(&self) -> f64 {
+        self.window().map_or(1.0, |window| window.scale_factor())
+    }
+    /// Called when window is moved to monitor with different DPI, new physical size comes with the following resize.
+    fn on_scale_factor_changed(&mut self, _scale_factor: f64) {}
+    /// Called when window gets minimized or restored.
+    fn set_minimized(&mut self, _minimized: bool) {}
+    /// Called when window gains or loses input focus.
+    fn set_focused(&mut self, _focused: bool) {}
     fn on_draw(&mut self, renderer: &mut Self::Renderer, draw_data: &imgui::DrawData);
 }
 
@@ -32,8 +66,59 @@ pub trait Manager: Sized {
 
     fn viewport(&self, wid: WindowId) -> Option<&Self::Viewport>;
     fn viewport_mut(&mut self, wid: WindowId) -> Option<&mut Self::Viewport>;
-    fn add_window(&mut self, window: Window) -> WindowId;
-    fn destroy(&mut self, wid: WindowId);
+    fn viewports(&self) -> Box<dyn Iterator<Item = (&WindowId, &Self::Viewport)> + '_>;
+    fn add_window(&mut self, window: Window) -> Result<WindowId, ManagerError>;
+    /// Removes viewport of the window, returns `false` if manager keeps it (e.g. main window)
+    /// or doesn't know it.
+    fn destroy(&mut self, wid: WindowId) -> bool;
+    /// Window of the primary viewport, closing it means closing the application.
+    fn main_window(&self) -> Option<WindowId> {
+        None
+    }
+    /// Passed to `WindowSpawner::build_window` for every spawned viewport window.
+    fn window_builder_hook(&mut self) -> Option<&mut WindowBuilderHook> {
+        None
+    }
+    /// How many monitors are reported to imgui, `None` for all of them.
+    fn monitor_limit(&self) -> Option<usize> {
+        Some(DEFAULT_MONITOR_LIMIT)
+    }
+
+    /// Keeps viewport in sync with its window (resizes, minimization) and reports close requests.
+    fn handle_window_event(&mut self, wid: WindowId, event: &WindowEvent) -> EventOutcome {
+        let is_main = self.main_window() == Some(wid);
+        let viewport = match self.viewport_mut(wid) {
+            Some(viewport) => viewport,
+            None => return EventOutcome::Ignored,
+        };
+        let minimized = minimized_by_event(event);
+        if let Some(minimized) = minimized {
+            viewport.set_minimized(minimized);
+        }
+        match event {
+            WindowEvent::Resized(_) if minimized == Some(false) => viewport.on_resize(),
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                viewport.on_scale_factor_changed(*scale_factor)
+            }
+            WindowEvent::Focused(focused) => viewport.set_focused(*focused),
+            WindowEvent::CloseRequested if is_main => return EventOutcome::Exit,
+            WindowEvent::CloseRequested => return EventOutcome::CloseRequested,
+            _ => {}
+        }
+        EventOutcome::Handled
+    }
+    /// Lifecycle event of managed window, close of the main window is reported too.
+    fn viewport_event(&self, wid: WindowId, event: &WindowEvent) -> Option<ViewportEvent> {
+        self.viewport(wid)?;
+        Some(match *event {
+            WindowEvent::CloseRequested => ViewportEvent::CloseRequested(wid),
+            WindowEvent::Moved(pos) => ViewportEvent::Moved(wid, pos),
+            WindowEvent::Resized(size) => ViewportEvent::Resized(wid, size),
+            WindowEvent::Focused(true) => ViewportEvent::FocusGained(wid),
+            WindowEvent::Focused(false) => ViewportEvent::FocusLost(wid),
+            _ => return None,
+        })
+    }
 
     fn with_loop<'a, T: 'static>(
         &'a mut self,
@@ -48,25 +133,95 @@ pub trait Manager: Sized {
     ) -> WithLoop<'a, Self, T, S> {
         WithLoop {
             manager: self,
-            event_loop,
+            event_loop: Some(event_loop),
             spawner,
         }
     }
 }
 
+/// What caller has to do after `Manager::handle_window_event`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventOutcome {
+    /// Window isn't managed.
+    Ignored,
+    Handled,
+    /// Secondary viewport window wants to be closed, it's up to imgui to destroy it.
+    CloseRequested,
+    /// Main window wants to be closed.
+    Exit,
+}
+
+/// Window lifecycle events, see `Manager::viewport_event`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewportEvent {
+    CloseRequested(WindowId),
+    Moved(WindowId, PhysicalPosition<i32>),
+    Resized(WindowId, PhysicalSize<u32>),
+    FocusGained(WindowId),
+    FocusLost(WindowId),
+}
+
+/// Minimization state reported by event, if any.
+///
+/// winit has no minimize event: minimized windows are resized to zero,
+/// and on Windows are also moved to (-32000, -32000).
+pub(crate) fn minimized_by_event(event: &WindowEvent) -> Option<bool> {
+    match event {
+        WindowEvent::Resized(size) => Some(size.width == 0 && size.height == 0),
+        #[cfg(windows)]
+        WindowEvent::Moved(pos) => Some(pos.x == -32000 && pos.y == -32000),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManagerError {
+    /// Manager already has a viewport for this window.
+    DuplicateWindow(WindowId),
+    /// Viewport without window can't have zero width or height.
+    ZeroSize,
+}
+impl fmt::Display for ManagerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManagerError::DuplicateWindow(wid) => write!(f, "window {:?} is already added", wid),
+            ManagerError::ZeroSize => write!(f, "viewport without window has zero size"),
+        }
+    }
+}
+impl std::error::Error for ManagerError {}
+
+/// Monitors reported to imgui unless `Manager::monitor_limit` says otherwise.
+pub const DEFAULT_MONITOR_LIMIT: usize = 32;
+
 pub struct WithLoop<'a, M, T: 'static, S = DefaultSpawner> {
     manager: &'a mut M,
-    event_loop: &'a EventLoopWindowTarget<T>,
+    /// `None` only in tests, they don't spawn windows.
+    event_loop: Option<&'a EventLoopWindowTarget<T>>,
     spawner: S,
 }
 
 impl<'a, M: Manager, T, S: WindowSpawner<M::Viewport>> WithLoop<'a, M, T, S> {
-    fn spawn_window(&mut self, flags: ViewportFlags) -> WindowId {
-        let window = self.spawner.build_window(self.event_loop, flags);
+    fn spawn_window(&mut self, flags: ViewportFlags) -> Result<WindowId, ManagerError> {
+        let event_loop = self.event_loop.expect("No event loop to spawn window");
+        let hook = self.manager.window_builder_hook();
+        let window = self.spawner.build_window(event_loop, flags, hook);
         self.manager.add_window(window)
     }
 }
 
+#[cfg(test)]
+impl<'a, M: Manager, S: WindowSpawner<M::Viewport>> WithLoop<'a, M, (), S> {
+    /// Dispatches platform commands without display, windows can't be spawned.
+    pub(crate) fn headless(manager: &'a mut M, spawner: S) -> Self {
+        WithLoop {
+            manager,
+            event_loop: None,
+            spawner,
+        }
+    }
+}
+
 impl<'a, M, T: 'static, S> Deref for WithLoop<'a, M, T, S> {
     type Target = M;
     fn deref(&self) -> &Self::Target {
@@ -79,13 +234,36 @@ impl<'a, M, T: 'static, S> DerefMut for WithLoop<'a, M, T, S> {
     }
 }
 
+/// Customizes viewport window (icon, size limits, resizability) before it's built.
+pub type WindowBuilderHook = Box<dyn FnMut(WindowBuilder, ViewportFlags) -> WindowBuilder>;
+
 pub trait WindowSpawner<V: Viewport> {
+    /// `hook` should be applied to the builder right before `build`.
     fn build_window<T: 'static>(
         &mut self,
         event_loop: &EventLoopWindowTarget<T>,
         flags: ViewportFlags,
+        hook: Option<&mut WindowBuilderHook>,
     ) -> Window;
+    /// Windows can be built invisible, `show_window` is called after imgui placed them.
     fn show_window(&mut self, viewport: &V);
+    /// Called when imgui wants to bring viewport window to the front.
+    ///
+    /// winit 0.23 can't request input focus, so default implementation only restores and shows the window.
+    fn focus_window(&mut self, viewport: &V) {
+        if let Some(window) = viewport.window() {
+            window.set_minimized(false);
+            window.set_visible(true);
+        }
+    }
+    /// Called when imgui changes viewport alpha (e.g. while dragging docked window).
+    ///
+    /// Default implementation uses `set_window_opacity`.
+    fn set_window_alpha(&mut self, viewport: &V, alpha: f32) {
+        if let Some(window) = viewport.window() {
+            set_window_opacity(window, alpha);
+        }
+    }
 }
 pub struct DefaultSpawner;
 impl<V: Viewport> WindowSpawner<V> for DefaultSpawner {
@@ -93,18 +271,100 @@ impl<V: Viewport> WindowSpawner<V> for DefaultSpawner {
         &mut self,
         event_loop: &EventLoopWindowTarget<T>,
         flags: ViewportFlags,
+        hook: Option<&mut WindowBuilderHook>,
     ) -> Window {
         let decorations = !flags.contains(ViewportFlags::NO_DECORATIONS);
-        WindowBuilder::new()
+        // undecorated viewports are popups, tooltips and dragged windows, the only ones imgui makes translucent
+        // shown by `show_window` once imgui has placed it
+        let builder = WindowBuilder::new()
+            .with_visible(false)
             .with_decorations(decorations)
-            .build(event_loop)
-            .unwrap()
+            .with_transparent(!decorations)
+            .with_always_on_top(flags.contains(ViewportFlags::TOPMOST));
+        let builder = skip_taskbar(builder, flags.contains(ViewportFlags::NO_TASK_BAR_ICON));
+        let builder = match hook {
+            Some(hook) => hook(builder, flags),
+            None => builder,
+        };
+        builder.build(event_loop).unwrap()
     }
     fn show_window(&mut self, viewport: &V) {
-        viewport.window().set_visible(true);
+        if let Some(window) = viewport.window() {
+            window.set_visible(true);
+        }
     }
 }
 
+/// Utility windows are kept out of taskbar by most X11 window managers.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+fn skip_taskbar(builder: WindowBuilder, skip: bool) -> WindowBuilder {
+    use winit::platform::unix::{WindowBuilderExtUnix, XWindowType};
+    if skip {
+        builder.with_x11_window_type(vec![XWindowType::Utility])
+    } else {
+        builder
+    }
+}
+
+/// winit 0.23 can't hide window from taskbar on other platforms.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+)))]
+fn skip_taskbar(builder: WindowBuilder, _skip: bool) -> WindowBuilder {
+    builder
+}
+
+/// Makes the whole window translucent, returns `false` if platform can't do it.
+///
+/// winit can make window transparent only at creation time, this goes through native handles instead.
+/// Only Windows is supported (layered window), elsewhere windows built with `with_transparent(true)`
+/// are blended by compositor using framebuffer alpha and the rest stay opaque.
+#[cfg(windows)]
+pub fn set_window_opacity(window: &Window, opacity: f32) -> bool {
+    use winapi::{
+        shared::windef::HWND,
+        um::winuser::{
+            GetWindowLongW, SetLayeredWindowAttributes, SetWindowLongW, GWL_EXSTYLE, LWA_ALPHA,
+            WS_EX_LAYERED,
+        },
+    };
+    use winit::platform::windows::WindowExtWindows;
+
+    let hwnd = window.hwnd() as HWND;
+    let alpha = (opacity.max(0.0).min(1.0) * 255.0).round() as u8;
+    let layered = WS_EX_LAYERED as i32;
+    unsafe {
+        let style = GetWindowLongW(hwnd, GWL_EXSTYLE);
+        if alpha == u8::MAX {
+            // layered windows are composed slower, drop the style when opaque again
+            if style & layered != 0 {
+                SetWindowLongW(hwnd, GWL_EXSTYLE, style & !layered);
+            }
+            return true;
+        }
+        if style & layered == 0 {
+            SetWindowLongW(hwnd, GWL_EXSTYLE, style | layered);
+        }
+        SetLayeredWindowAttributes(hwnd, 0, alpha, LWA_ALPHA) != 0
+    }
+}
+
+/// Not supported: X11 `_NET_WM_WINDOW_OPACITY` needs Xlib calls this crate doesn't link.
+#[cfg(not(windows))]
+pub fn set_window_opacity(_window: &Window, _opacity: f32) -> bool {
+    false
+}
+
 //use imgui_sys::ImGuiWindowFlags;
 
 bitflags! {
@@ -1,22 +1,37 @@
 use winit::{
+    dpi::{PhysicalPosition, PhysicalSize, Size},
     event::{
         ElementState, Event, KeyboardInput, MouseButton, MouseScrollDelta, TouchPhase,
         VirtualKeyCode, WindowEvent,
     },
-    window::WindowId,
+    monitor::MonitorHandle,
+    window::{CursorIcon, WindowId},
 };
 
-use imgui::{sys as imgui_sys, BackendFlags, Context, ImString, Io, Key, Ui};
+use imgui::{
+    sys as imgui_sys, BackendFlags, ConfigFlags, Context, ImString, Io, Key as ImKey, MouseCursor,
+    Ui,
+};
 use imgui_sys::{ImGuiPlatformIO, ImGuiViewport};
 use std::{
-    cmp::Ordering,
+    fmt,
+    path::PathBuf,
     rc::Rc,
     time::{Duration, Instant},
 };
 
+use crate::ViewportFlags;
+
 mod callbacks;
+#[cfg(feature = "clipboard")]
+mod clipboard;
+mod focus;
 mod proxy;
+#[cfg(feature = "clipboard")]
+pub use clipboard::register_clipboard;
+pub use focus::{focus_order, focused_viewport_keys, FocusOrder, TopLevelFocusOrder};
 use proxy::{Cache, Proxy, SharedProxy};
+pub use proxy::{Key, ProxyError};
 
 /// winit backend platform state
 #[derive(Debug)]
@@ -24,6 +39,23 @@ pub struct Platform {
     main_view: WindowId,
     proxy: SharedProxy,
     last_frame: Instant,
+    dropped_files: Vec<DroppedFile>,
+    hovered_files: Vec<DroppedFile>,
+    /// Window under the mouse cursor.
+    cursor_window: Option<WindowId>,
+    /// Cursor last applied to window, to avoid calling winit every frame.
+    last_cursor: Option<(WindowId, Option<MouseCursor>)>,
+    wheel_line_height: f64,
+}
+
+/// File dragged onto one of the viewport windows.
+#[derive(Debug, Clone)]
+pub struct DroppedFile {
+    /// Window under the cursor, the one that received the file.
+    pub window: WindowId,
+    /// imgui viewport of that window, `None` if window isn't managed by imgui.
+    pub key: Option<Key>,
+    pub path: PathBuf,
 }
 
 impl Platform {
@@ -34,51 +66,57 @@ impl Platform {
     /// * backend flags are updated
     /// * keys are configured
     /// * platform name is set
+    /// # Panics
+    /// If `main_view` has no window.
     pub fn init<V: crate::Viewport>(imgui: &mut Context, main_view: &V) -> Platform {
+        let window = main_view.window().expect("Main viewport has no window");
+        Self::init_window(imgui, window.id(), window.inner_size())
+    }
+    /// `init` for main viewport without window (e.g. `WgpuManager::add_offscreen`),
+    /// `size` is its display size.
+    pub fn init_offscreen(
+        imgui: &mut Context,
+        main_view: WindowId,
+        size: PhysicalSize<u32>,
+    ) -> Platform {
+        Self::init_window(imgui, main_view, size)
+    }
+    fn init_window(imgui: &mut Context, main_view: WindowId, size: PhysicalSize<u32>) -> Platform {
         imgui.set_platform_name(Some(ImString::from(format!(
             "imgui-winit-support-viewports {}",
             env!("CARGO_PKG_VERSION")
         ))));
 
         let io = imgui.io_mut();
-        let has_viewports = unsafe {
-            BackendFlags::from_bits_unchecked(
-                imgui_sys::ImGuiBackendFlags_PlatformHasViewports
-                    | imgui_sys::ImGuiBackendFlags_RendererHasViewports,
-            )
-        };
-        io.backend_flags.insert(has_viewports);
+        io.backend_flags.insert(has_viewports());
         io.backend_flags.insert(BackendFlags::HAS_MOUSE_CURSORS);
         //io.backend_flags.insert(BackendFlags::HAS_SET_MOUSE_POS);
 
-        io[Key::Tab] = VirtualKeyCode::Tab as _;
-        io[Key::LeftArrow] = VirtualKeyCode::Left as _;
-        io[Key::RightArrow] = VirtualKeyCode::Right as _;
-        io[Key::UpArrow] = VirtualKeyCode::Up as _;
-        io[Key::DownArrow] = VirtualKeyCode::Down as _;
-        io[Key::PageUp] = VirtualKeyCode::PageUp as _;
-        io[Key::PageDown] = VirtualKeyCode::PageDown as _;
-        io[Key::Home] = VirtualKeyCode::Home as _;
-        io[Key::End] = VirtualKeyCode::End as _;
-        io[Key::Insert] = VirtualKeyCode::Insert as _;
-        io[Key::Delete] = VirtualKeyCode::Delete as _;
-        io[Key::Backspace] = VirtualKeyCode::Back as _;
-        io[Key::Space] = VirtualKeyCode::Space as _;
-        io[Key::Enter] = VirtualKeyCode::Return as _;
-        io[Key::Escape] = VirtualKeyCode::Escape as _;
-        io[Key::KeyPadEnter] = VirtualKeyCode::NumpadEnter as _;
-        io[Key::A] = VirtualKeyCode::A as _;
-        io[Key::C] = VirtualKeyCode::C as _;
-        io[Key::V] = VirtualKeyCode::V as _;
-        io[Key::X] = VirtualKeyCode::X as _;
-        io[Key::Y] = VirtualKeyCode::Y as _;
-        io[Key::Z] = VirtualKeyCode::Z as _;
+        io[ImKey::Tab] = VirtualKeyCode::Tab as _;
+        io[ImKey::LeftArrow] = VirtualKeyCode::Left as _;
+        io[ImKey::RightArrow] = VirtualKeyCode::Right as _;
+        io[ImKey::UpArrow] = VirtualKeyCode::Up as _;
+        io[ImKey::DownArrow] = VirtualKeyCode::Down as _;
+        io[ImKey::PageUp] = VirtualKeyCode::PageUp as _;
+        io[ImKey::PageDown] = VirtualKeyCode::PageDown as _;
+        io[ImKey::Home] = VirtualKeyCode::Home as _;
+        io[ImKey::End] = VirtualKeyCode::End as _;
+        io[ImKey::Insert] = VirtualKeyCode::Insert as _;
+        io[ImKey::Delete] = VirtualKeyCode::Delete as _;
+        io[ImKey::Backspace] = VirtualKeyCode::Back as _;
+        io[ImKey::Space] = VirtualKeyCode::Space as _;
+        io[ImKey::Enter] = VirtualKeyCode::Return as _;
+        io[ImKey::Escape] = VirtualKeyCode::Escape as _;
+        io[ImKey::KeyPadEnter] = VirtualKeyCode::NumpadEnter as _;
+        io[ImKey::A] = VirtualKeyCode::A as _;
+        io[ImKey::C] = VirtualKeyCode::C as _;
+        io[ImKey::V] = VirtualKeyCode::V as _;
+        io[ImKey::X] = VirtualKeyCode::X as _;
+        io[ImKey::Y] = VirtualKeyCode::Y as _;
+        io[ImKey::Z] = VirtualKeyCode::Z as _;
 
         io.display_framebuffer_scale = [1.0, 1.0];
-        {
-            let size = main_view.window().inner_size();
-            io.display_size = [size.width as f32, size.height as f32];
-        }
+        io.display_size = [size.width as f32, size.height as f32];
         //io.display_framebuffer_scale = [hidpi_factor as f32, hidpi_factor as f32];
         //let logical_size = window.inner_size().to_logical(hidpi_factor);
         //let logical_size = self.scale_size_from_winit(window, logical_size);
@@ -88,7 +126,6 @@ impl Platform {
         //cache.insert(main_view, Cache::default());
 
         let proxy = Proxy::shared();
-        let main_view = main_view.window().id();
         let main_view_key = proxy.borrow_mut().use_window(main_view);
 
         unsafe {
@@ -100,8 +137,11 @@ impl Platform {
         callbacks::register_platform_callbacks(platform_io);
 
         unsafe {
-            (*platform_io.MainViewport).PlatformUserData = main_view_key as _;
+            (*platform_io.MainViewport).PlatformUserData = main_view_key.into_ptr();
         }
+        proxy
+            .borrow_mut()
+            .bind_viewport(main_view_key, platform_io.MainViewport);
 
         /*assert_eq!(std::mem::size_of::<WindowId>(), std::mem::size_of::<usize>());
         unsafe {
@@ -119,9 +159,23 @@ impl Platform {
             main_view,
             proxy,
             last_frame,
+            dropped_files: vec![],
+            hovered_files: vec![],
+            cursor_window: None,
+            last_cursor: None,
+            wheel_line_height: DEFAULT_WHEEL_LINE_HEIGHT,
         }
     }
 
+    /// `init` preceded by `check_data_layout`.
+    pub fn init_checked<V: crate::Viewport>(
+        imgui: &mut Context,
+        main_view: &V,
+    ) -> Result<Platform, LayoutMismatch> {
+        check_data_layout()?;
+        Ok(Self::init(imgui, main_view))
+    }
+
     pub fn handle_event<T, M: crate::Manager>(
         &mut self,
         io: &mut Io,
@@ -137,18 +191,109 @@ impl Platform {
                 let main_view = self.main_view;
                 if let Some(viewport) = viewport {
                     let mut proxy = self.proxy.borrow_mut();
-                    let cache = proxy.expect_cache_by_wid(window_id).1;
-                    Self::handle_window_event(io, viewport, cache, event);
-                    if window_id == main_view {
-                        Self::handle_main_view_event(io, viewport, cache, event);
+                    if let Some((&key, cache)) = proxy.cache_by_wid(window_id) {
+                        Self::handle_window_event(io, viewport, key, cache, event);
+                        if window_id == main_view {
+                            Self::handle_main_view_event(io, viewport, cache, event);
+                        } else if let WindowEvent::CloseRequested = event {
+                            // imgui closes windows hosted by viewport and destroys it through callbacks
+                            if let Some(vp) = unsafe { viewport_by_key(key) } {
+                                vp.PlatformRequestClose = true;
+                            }
+                        }
+                    }
+                    if let WindowEvent::Focused(true) = event {
+                        proxy.set_focused_window(window_id);
                     }
                 }
+                self.handle_file_event(window_id, event);
+                self.handle_cursor_event(window_id, event);
                 self.handle_global_event(io, event);
             }
             _ => (),
         }
     }
 
+    fn handle_file_event(&mut self, window: WindowId, event: &WindowEvent) {
+        match event {
+            WindowEvent::HoveredFile(path) => {
+                let key = self.key_for_window(window);
+                self.hovered_files.push(DroppedFile {
+                    window,
+                    key,
+                    path: path.clone(),
+                });
+            }
+            WindowEvent::HoveredFileCancelled => {
+                self.hovered_files.retain(|file| file.window != window);
+            }
+            WindowEvent::DroppedFile(path) => {
+                self.hovered_files.retain(|file| file.window != window);
+                let key = self.key_for_window(window);
+                self.dropped_files.push(DroppedFile {
+                    window,
+                    key,
+                    path: path.clone(),
+                });
+            }
+            _ => {}
+        }
+    }
+    fn handle_cursor_event(&mut self, window: WindowId, event: &WindowEvent) {
+        match event {
+            WindowEvent::CursorEntered { .. } | WindowEvent::CursorMoved { .. } => {
+                self.cursor_window = Some(window);
+            }
+            WindowEvent::CursorLeft { .. } if self.cursor_window == Some(window) => {
+                self.cursor_window = None;
+            }
+            _ => {}
+        }
+    }
+    /// Applies imgui cursor to the viewport window under the mouse, hides it if imgui draws cursor itself.
+    fn update_cursor<M: crate::Manager>(
+        &mut self,
+        io: &Io,
+        cursor: Option<MouseCursor>,
+        manager: &M,
+    ) {
+        if io
+            .config_flags
+            .contains(ConfigFlags::NO_MOUSE_CURSOR_CHANGE)
+        {
+            return;
+        }
+        let wid = match self.cursor_window {
+            Some(wid) => wid,
+            None => return,
+        };
+        if self.last_cursor == Some((wid, cursor)) {
+            return;
+        }
+        if let Some(window) = manager.viewport(wid).and_then(|viewport| viewport.window()) {
+            match cursor {
+                Some(cursor) if !io.mouse_draw_cursor => {
+                    window.set_cursor_visible(true);
+                    window.set_cursor_icon(to_winit_cursor(cursor));
+                }
+                _ => window.set_cursor_visible(false),
+            }
+            self.last_cursor = Some((wid, cursor));
+        }
+    }
+    /// Pixels of touchpad scrolling that make one wheel step.
+    pub fn set_wheel_line_height(&mut self, line_height: f64) {
+        self.wheel_line_height = line_height;
+    }
+    /// Files dropped since the last drain, in drop order.
+    pub fn drain_dropped_files(&mut self) -> impl Iterator<Item = DroppedFile> + '_ {
+        self.dropped_files.drain(..)
+    }
+    /// Files currently dragged over viewport windows.
+    pub fn hovered_files(&self) -> &[DroppedFile] {
+        &self.hovered_files
+    }
+
     fn handle_main_view_event<V: crate::Viewport>(
         io: &mut Io,
         _viewport: &mut V,
@@ -166,13 +311,16 @@ impl Platform {
     fn handle_window_event<V: crate::Viewport>(
         io: &mut Io,
         viewport: &mut V,
+        key: Key,
         cache: &mut Cache,
         event: &WindowEvent,
     ) {
         match *event {
-            WindowEvent::ScaleFactorChanged {
-                scale_factor: _, ..
-            } => {
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                viewport.on_scale_factor_changed(scale_factor);
+                if let Some(vp) = unsafe { viewport_by_key(key) } {
+                    vp.DpiScale = scale_factor as f32;
+                }
                 /*let hidpi_factor = match self.hidpi_mode {
                     ActiveHiDpiMode::Default => scale_factor,
                     ActiveHiDpiMode::Rounded => scale_factor.round(),
@@ -193,48 +341,39 @@ impl Platform {
                 let logical_size = self.scale_size_from_winit(window, logical_size);
                 io.display_size = [logical_size.width as f32, logical_size.height as f32];*/
             }
-            WindowEvent::KeyboardInput {
-                input:
-                    KeyboardInput {
-                        virtual_keycode: Some(key),
-                        state: ElementState::Pressed,
-                        ..
-                    },
-                ..
-            } => {
-                io.keys_down[key as usize] = true;
-            }
-            WindowEvent::ReceivedCharacter(ch) => {
-                // Exclude the backspace key ('\u{7f}'). Otherwise we will insert this char and then
-                // delete it.
-                if ch != '\u{7f}' {
-                    io.add_input_character(ch)
-                }
-            }
             WindowEvent::Focused(focus) => {
                 cache.focus = focus;
+                viewport.set_focused(focus);
             }
             WindowEvent::Moved(pos) => {
-                #[cfg(windows)]
-                {
-                    if pos == [-32000, -32000].into() {
-                        cache.minimized = true;
-                    } else {
-                        cache.minimized = false;
-                    }
+                if let Some(minimized) = crate::minimized_by_event(event) {
+                    cache.minimized = minimized;
+                    viewport.set_minimized(minimized);
                 }
                 if !cache.minimized {
+                    // don't report imgui's own `Platform_SetWindowPos` back to it
+                    let user_moved = !cache.is_pos_echo(pos);
                     cache.set_pos(pos);
+                    if user_moved {
+                        if let Some(vp) = unsafe { viewport_by_key(key) } {
+                            vp.PlatformRequestMove = true;
+                        }
+                    }
                 }
             }
             WindowEvent::Resized(size) => {
-                if size == [0, 0].into() {
-                    cache.minimized = true;
-                } else {
-                    cache.minimized = false;
+                cache.minimized = crate::minimized_by_event(event).unwrap_or(false);
+                if !cache.minimized {
+                    let user_resized = !cache.is_size_echo(size);
                     cache.set_size(size);
                     viewport.on_resize();
+                    if user_resized {
+                        if let Some(vp) = unsafe { viewport_by_key(key) } {
+                            vp.PlatformRequestResize = true;
+                        }
+                    }
                 }
+                viewport.set_minimized(cache.minimized);
             }
             WindowEvent::CursorMoved { position, .. } => {
                 /*
@@ -242,74 +381,31 @@ impl Platform {
                 let position = self.scale_pos_from_winit(window, position);
                 io.mouse_pos = [position.x as f32, position.y as f32];
                 */
-                let position = position.cast::<f32>();
-                let winpos = viewport.window().outer_position().unwrap().cast::<f32>();
-                io.mouse_pos = [position.x + winpos.x, position.y + winpos.y];
+                if !io.config_flags.contains(ConfigFlags::VIEWPORTS_ENABLE) {
+                    // main viewport sits at origin without multi-viewports
+                    io.mouse_pos = [position.x as f32, position.y as f32];
+                } else if let Some(mouse_pos) = desktop_mouse_pos(viewport, position) {
+                    io.mouse_pos = mouse_pos;
+                }
             }
             WindowEvent::CursorLeft { .. } => {
                 io.mouse_pos = [f32::MIN, f32::MIN];
             }
-            WindowEvent::MouseWheel {
-                delta,
-                phase: TouchPhase::Moved,
-                ..
-            } => match delta {
-                MouseScrollDelta::LineDelta(h, v) => {
-                    io.mouse_wheel_h = h;
-                    io.mouse_wheel = v;
-                }
-                MouseScrollDelta::PixelDelta(pos) => {
-                    //let pos = pos.to_logical::<f64>(self.hidpi_factor);
-                    match pos.x.partial_cmp(&0.0) {
-                        Some(Ordering::Greater) => io.mouse_wheel_h += 1.0,
-                        Some(Ordering::Less) => io.mouse_wheel_h -= 1.0,
-                        _ => (),
-                    }
-                    match pos.y.partial_cmp(&0.0) {
-                        Some(Ordering::Greater) => io.mouse_wheel += 1.0,
-                        Some(Ordering::Less) => io.mouse_wheel -= 1.0,
-                        _ => (),
-                    }
-                }
-            },
             WindowEvent::MouseInput { state, button, .. } => {
-                let pressed = state == ElementState::Pressed;
-                match button {
-                    MouseButton::Left => io.mouse_down[0] = pressed,
-                    MouseButton::Right => io.mouse_down[1] = pressed,
-                    MouseButton::Middle => io.mouse_down[2] = pressed,
-                    MouseButton::Other(idx @ 0..=4) => io.mouse_down[idx as usize] = pressed,
-                    _ => (),
-                }
+                set_mouse_button(io, button, state == ElementState::Pressed);
             }
             _ => (),
         }
     }
     fn handle_global_event(&mut self, io: &mut Io, event: &WindowEvent) {
         match *event {
-            WindowEvent::KeyboardInput {
-                input:
-                    KeyboardInput {
-                        virtual_keycode: Some(key),
-                        state: ElementState::Released,
-                        ..
-                    },
+            WindowEvent::KeyboardInput { ref input, .. } => handle_keyboard(io, input),
+            WindowEvent::ReceivedCharacter(ch) => handle_character(io, ch),
+            WindowEvent::MouseWheel {
+                delta,
+                phase: TouchPhase::Moved,
                 ..
-            } => {
-                io.keys_down[key as usize] = false;
-
-                // This is a bit redundant here, but we'll leave it in. The OS occasionally
-                // fails to send modifiers keys, but it doesn't seem to send false-positives,
-                // so double checking isn't terrible in case some system *doesn't* send
-                // device events sometimes.
-                match key {
-                    VirtualKeyCode::LShift | VirtualKeyCode::RShift => io.key_shift = false,
-                    VirtualKeyCode::LControl | VirtualKeyCode::RControl => io.key_ctrl = false,
-                    VirtualKeyCode::LAlt | VirtualKeyCode::RAlt => io.key_alt = false,
-                    VirtualKeyCode::LWin | VirtualKeyCode::RWin => io.key_super = false,
-                    _ => (),
-                }
-            }
+            } => handle_mouse_wheel(io, delta, self.wheel_line_height),
             WindowEvent::ModifiersChanged(modifiers) => {
                 io.key_shift = modifiers.shift();
                 io.key_ctrl = modifiers.ctrl();
@@ -329,35 +425,176 @@ impl Platform {
         imgui: &mut Context,
         manager: &mut crate::WithLoop<M, T, S>,
         frame: F,
-    ) {
-        update_monitors(manager, imgui.platform_io());
+    ) -> Result<(), ProxyError> {
+        let viewports = viewports_enabled(imgui);
+        if viewports {
+            update_monitors(manager, imgui.platform_io());
+        }
 
         let now = Instant::now();
         let delta_s = now - self.last_frame;
         imgui.io_mut().update_delta_time(delta_s);
         self.last_frame = now;
 
-        self.proxy.borrow_mut().update(manager);
+        let before_frame = self.proxy.borrow_mut().update(manager);
 
         let ui = imgui.frame();
         frame(&ui, delta_s);
+        let cursor = ui.mouse_cursor();
         let _ = ui.render();
 
-        self.proxy.borrow_mut().update(manager);
+        let after_render = self.proxy.borrow_mut().update(manager);
 
-        /*if last_cursor != Some(ui.mouse_cursor()) {
-            last_cursor = Some(ui.mouse_cursor());
-            platform.prepare_render(&ui, active.expect_native_window(first_id));
-        }*/
+        self.update_cursor(imgui.io(), cursor, &**manager);
+        if !viewports {
+            return before_frame.and(after_render);
+        }
         imgui.update_platform_windows();
-        self.proxy.borrow_mut().update(manager);
+        self.sync_viewport_flags(imgui.platform_io());
+        // windows for viewports created this frame must exist before draw data is dispatched
+        let after_update = self.proxy.borrow_mut().update(manager);
+        before_frame.and(after_render).and(after_update)
     }
+    /// Runs the whole frame: `frame`, then draws every viewport with `render_all`.
+    ///
+    /// Use it from `Event::MainEventsCleared` when there is no need to wait for `RedrawRequested`.
+    pub fn run_frame<
+        T,
+        M: crate::Manager,
+        F: FnOnce(&Ui, Duration),
+        S: super::WindowSpawner<M::Viewport>,
+    >(
+        &mut self,
+        imgui: &mut Context,
+        manager: &mut crate::WithLoop<M, T, S>,
+        renderer: &mut M::Renderer,
+        frame: F,
+    ) -> Result<(), ProxyError> {
+        let result = self.frame(imgui, manager, frame);
+        self.render_all(imgui, &mut **manager, renderer);
+        result
+    }
+    /// Draws every viewport that has draw data, returns how many were drawn.
+    ///
+    /// Main viewport is drawn directly, the rest through `render_platform_windows`.
+    pub fn render_all<M: crate::Manager>(
+        &self,
+        imgui: &mut Context,
+        manager: &mut M,
+        renderer: &mut M::Renderer,
+    ) -> usize {
+        let mut rendered = 0;
+        if let Some(draw_data) = self.draw_data(imgui, self.main_view) {
+            if let Some(viewport) = manager.viewport_mut(self.main_view) {
+                viewport.on_draw(renderer, draw_data);
+                rendered += 1;
+            }
+        }
+        if viewports_enabled(imgui) {
+            rendered += self.render_platform_windows(imgui, manager, renderer);
+        }
+        rendered
+    }
+    /// Draws secondary viewports with `igRenderPlatformWindowsDefault`,
+    /// `Renderer_RenderWindow` calls `Viewport::on_draw`. Returns how many were drawn.
+    ///
+    /// Main viewport is not drawn, same as in other imgui backends.
+    pub fn render_platform_windows<M: crate::Manager>(
+        &self,
+        imgui: &mut Context,
+        manager: &mut M,
+        renderer: &mut M::Renderer,
+    ) -> usize {
+        let proxy = self.proxy.borrow();
+        let mut arg = callbacks::RenderArg {
+            proxy: &proxy,
+            manager,
+            renderer,
+            rendered: 0,
+        };
+        callbacks::register_renderer_callbacks::<M>(imgui.platform_io());
+        unsafe {
+            imgui_sys::igRenderPlatformWindowsDefault(
+                std::ptr::null_mut(),
+                &mut arg as *mut callbacks::RenderArg<M> as *mut std::ffi::c_void,
+            );
+        }
+        callbacks::unregister_renderer_callbacks(imgui.platform_io());
+        arg.rendered
+    }
+    /// Calls `f` for every spawned and not minimized viewport that has draw data,
+    /// returns how many times it was called.
+    ///
+    /// Viewports imgui created this frame are skipped until their window is spawned.
+    pub fn for_each_viewport_draw_data<M: crate::Manager, F>(
+        &self,
+        imgui: &mut Context,
+        manager: &mut M,
+        mut f: F,
+    ) -> usize
+    where
+        F: FnMut(&mut M::Viewport, &imgui::DrawData),
+    {
+        use imgui::internal::RawCast;
+        let platform = imgui.platform_io();
+        let proxy = self.proxy.borrow();
+        let mut count = 0;
+        unsafe {
+            if platform.Viewports.Data.is_null() {
+                return 0;
+            }
+            let viewports: &[*mut ImGuiViewport] =
+                std::slice::from_raw_parts(platform.Viewports.Data, platform.Viewports.Size as _);
+            for vp in viewports.iter().filter_map(|vp| vp.as_ref()) {
+                let key = Key::from_ptr(vp.PlatformUserData);
+                if key.is_unset() {
+                    continue;
+                }
+                let cache = match proxy.cache(key) {
+                    Some(cache) if !cache.minimized => cache,
+                    _ => continue,
+                };
+                let draw_data = match vp.DrawData.as_ref() {
+                    Some(draw_data) if draw_data.Valid => draw_data,
+                    _ => continue,
+                };
+                if let Some(viewport) = manager.viewport_mut(cache.wid) {
+                    f(viewport, RawCast::from_raw(draw_data));
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+    fn sync_viewport_flags(&self, platform: &ImGuiPlatformIO) {
+        let mut proxy = self.proxy.borrow_mut();
+        unsafe {
+            let viewports: &[*mut ImGuiViewport] =
+                std::slice::from_raw_parts(platform.Viewports.Data, platform.Viewports.Size as _);
+            for vp in viewports.iter().filter_map(|vp| vp.as_ref()) {
+                if vp.PlatformUserData.is_null() {
+                    continue;
+                }
+                let key = Key::from_ptr(vp.PlatformUserData);
+                let flags = ViewportFlags::from_bits_unchecked(vp.Flags as u32);
+                proxy.sync_top_most(key, flags.contains(ViewportFlags::TOPMOST));
+            }
+        }
+    }
+    /// Draw data of the viewport owning this window.
+    ///
+    /// `frame` spawns windows for viewports created during the frame before returning,
+    /// so every viewport with draw data has a window by the time it's rendered.
+    /// Viewports without window (e.g. `frame` failed) are skipped.
     pub fn draw_data<'a>(
         &self,
         imgui: &'a mut imgui::Context,
         wid: WindowId,
     ) -> Option<&'a imgui::DrawData> {
         use imgui::internal::RawCast;
+        if wid != self.main_view && !viewports_enabled(imgui) {
+            return None;
+        }
         let platform = imgui.platform_io();
         let mut proxy = self.proxy.borrow_mut();
         // first frame there can be no window
@@ -370,73 +607,436 @@ impl Platform {
             let viewports: &[*mut ImGuiViewport] =
                 std::slice::from_raw_parts(platform.Viewports.Data, platform.Viewports.Size as _);
             for vp in viewports.iter().filter_map(|vp| vp.as_ref()) {
-                if vp.PlatformUserData.is_null() {
+                let key = Key::from_ptr(vp.PlatformUserData);
+                if key.is_unset() || key != search_key {
                     continue;
                 }
-                let key: proxy::Key = std::mem::transmute(vp.PlatformUserData);
-                if key != search_key {
-                    continue;
-                }
-                let draw_data = RawCast::from_raw(vp.DrawData.as_ref()?);
-                return Some(draw_data);
+                let draw_data = vp.DrawData.as_ref().filter(|draw_data| draw_data.Valid)?;
+                return Some(RawCast::from_raw(draw_data));
             }
         }
         None
     }
+    /// Window of imgui viewport with this key, if it was already spawned.
+    pub fn window_id(&self, key: Key) -> Option<WindowId> {
+        self.proxy.borrow().window_id(key)
+    }
+    /// Key of imgui viewport that owns this window.
+    pub fn key_for_window(&self, wid: WindowId) -> Option<Key> {
+        self.proxy.borrow().key_for_window(wid)
+    }
+    /// imgui viewport of this window, e.g. to set `PlatformRequest*` flags from event handlers.
+    ///
+    /// Pointer is owned by imgui: it's valid until `Platform_DestroyWindow` of the viewport,
+    /// which happens inside `frame`, so don't keep it across frames.
+    pub fn viewport_ptr_for_window(&self, wid: WindowId) -> Option<*mut ImGuiViewport> {
+        self.proxy.borrow().viewport_ptr_for_window(wid)
+    }
+    /// `None` if viewport has no window yet.
+    pub fn try_get_focus(&self, key: Key) -> Option<bool> {
+        self.proxy.borrow().try_get_focus(key)
+    }
+    /// Outer position of viewport window, `None` if it isn't known yet.
+    pub fn try_get_position(&self, key: Key) -> Option<[f32; 2]> {
+        let pos = self.proxy.borrow().try_get_position(key)?;
+        Some([pos.x, pos.y])
+    }
+    /// Inner size of viewport window, `None` if it isn't known yet.
+    pub fn try_get_size(&self, key: Key) -> Option<[f32; 2]> {
+        let size = self.proxy.borrow().try_get_size(key)?;
+        Some([size.x, size.y])
+    }
+    /// Limits inner size of viewport window, imgui size requests are clamped into it.
+    pub fn set_size_constraints(&self, key: Key, min: Option<Size>, max: Option<Size>) {
+        self.proxy.borrow_mut().set_size_constraints(key, min, max);
+    }
+    /// Size constraints for viewport windows spawned after this call.
+    pub fn set_default_size_constraints(&self, min: Option<Size>, max: Option<Size>) {
+        self.proxy
+            .borrow_mut()
+            .set_default_size_constraints(min, max);
+    }
     pub fn last_frame(&self) -> Instant {
         self.last_frame
     }
 }
 
-fn update_monitors<M, T, S>(with_loop: &crate::WithLoop<M, T, S>, platform: &mut ImGuiPlatformIO) {
-    use imgui_sys::{ImGuiPlatformMonitor, ImVec2};
-    let mut monitors = if platform.Monitors.Data.is_null() {
-        Vec::with_capacity(with_loop.event_loop.available_monitors().size_hint().0)
+/// Compiled imgui disagrees with imgui-sys bindings on type sizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayoutMismatch;
+impl fmt::Display for LayoutMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "imgui {} data layout doesn't match imgui-sys bindings",
+            imgui::dear_imgui_version()
+        )
+    }
+}
+impl std::error::Error for LayoutMismatch {}
+
+/// Asks imgui to compare sizes of its core types with the ones of imgui-sys bindings.
+///
+/// imgui asserts on mismatch when built with assertions, otherwise the error is returned.
+/// Version is taken from imgui itself, only the layout is really checked.
+pub fn check_data_layout() -> Result<(), LayoutMismatch> {
+    use imgui_sys::{ImDrawIdx, ImDrawVert, ImGuiIO, ImGuiStyle, ImVec2, ImVec4};
+    use std::mem::size_of;
+    let matches = unsafe {
+        imgui_sys::igDebugCheckVersionAndDataLayout(
+            imgui_sys::igGetVersion(),
+            size_of::<ImGuiIO>(),
+            size_of::<ImGuiStyle>(),
+            size_of::<ImVec2>(),
+            size_of::<ImVec4>(),
+            size_of::<ImDrawVert>(),
+            size_of::<ImDrawIdx>(),
+        )
+    };
+    if matches {
+        Ok(())
     } else {
-        use std::mem::replace;
-        let raw = &mut platform.Monitors;
-        let ptr = replace(&mut raw.Data, std::ptr::null_mut());
-        let length = replace(&mut raw.Size, 0) as usize;
-        let capacity = replace(&mut raw.Capacity, 0) as usize;
-        assert!(length < 32);
-        assert!(capacity <= length);
-        unsafe { Vec::from_raw_parts(ptr, length, capacity) }
+        Err(LayoutMismatch)
+    }
+}
+
+/// Platform state of imgui context, for installing or wrapping platform callbacks.
+///
+/// `ImGuiPlatformIO` belongs to the current context, which `imgui` is as long as it isn't suspended,
+/// and the borrow of `imgui` keeps other references to it out while this one is alive.
+/// Callbacks installed by `Platform::init` expect `PlatformUserData` of viewports to stay untouched.
+pub fn platform_io(imgui: &mut Context) -> &mut ImGuiPlatformIO {
+    imgui.platform_io()
+}
+
+/// Shared variant of `platform_io`.
+pub fn platform_io_ref(_imgui: &Context) -> &ImGuiPlatformIO {
+    unsafe {
+        imgui_sys::igGetPlatformIO()
+            .as_ref()
+            .expect("ImGuiPlatformIO")
+    }
+}
+
+/// Undoes platform registration done by `Platform::init`, so the context can be initialized again.
+///
+/// Secondary imgui windows are destroyed, their native windows go away on the next
+/// `Platform::frame` if the old `Platform` is still used, otherwise together with the manager.
+pub fn unregister_platform(imgui: &mut Context) {
+    use imgui::internal::RawCast;
+    unsafe {
+        if imgui.io().raw().BackendPlatformUserData.is_null() {
+            return;
+        }
+        imgui_sys::igDestroyPlatformWindows();
+    }
+    let platform_io = imgui.platform_io();
+    callbacks::unregister_platform_callbacks(platform_io);
+    unsafe {
+        if let Some(main_viewport) = platform_io.MainViewport.as_mut() {
+            main_viewport.PlatformUserData = std::ptr::null_mut();
+        }
+        let io = imgui.io_mut().raw_mut();
+        let proxy: SharedProxy = Rc::from_raw(io.BackendPlatformUserData as _);
+        io.BackendPlatformUserData = std::ptr::null_mut();
+        drop(proxy);
+    }
+    // imgui asserts on handlers of advertised capabilities
+    let io = imgui.io_mut();
+    io.backend_flags.remove(has_viewports());
+    io.backend_flags.remove(BackendFlags::HAS_MOUSE_CURSORS);
+}
+
+fn has_viewports() -> BackendFlags {
+    unsafe {
+        BackendFlags::from_bits_unchecked(
+            imgui_sys::ImGuiBackendFlags_PlatformHasViewports
+                | imgui_sys::ImGuiBackendFlags_RendererHasViewports,
+        )
+    }
+}
+
+/// Turns on docking and multi-viewports together with backend flags they need.
+///
+/// Returns `false` if everything was already enabled.
+pub fn enable_docking_and_viewports(imgui: &mut Context) -> bool {
+    let io = imgui.io_mut();
+    let config = ConfigFlags::DOCKING_ENABLE | ConfigFlags::VIEWPORTS_ENABLE;
+    let backend = has_viewports();
+    if io.config_flags.contains(config) && io.backend_flags.contains(backend) {
+        return false;
+    }
+    io.config_flags.insert(config);
+    io.backend_flags.insert(backend);
+    true
+}
+
+/// Whether imgui may move windows out of the main viewport.
+///
+/// When it's off, `Platform` only deals with the main window.
+pub fn viewports_enabled(imgui: &Context) -> bool {
+    imgui
+        .io()
+        .config_flags
+        .contains(ConfigFlags::VIEWPORTS_ENABLE)
+}
+
+/// Reports cursor position over any viewport window to imgui in desktop coordinates.
+///
+/// Returns `false` if manager has no such window.
+pub fn handle_cursor_moved<M: crate::Manager>(
+    io: &mut Io,
+    manager: &M,
+    wid: WindowId,
+    position: PhysicalPosition<f64>,
+) -> bool {
+    match manager
+        .viewport(wid)
+        .and_then(|viewport| desktop_mouse_pos(viewport, position))
+    {
+        Some(mouse_pos) => {
+            io.mouse_pos = mouse_pos;
+            true
+        }
+        None => false,
+    }
+}
+
+fn desktop_mouse_pos<V: crate::Viewport>(
+    viewport: &V,
+    position: PhysicalPosition<f64>,
+) -> Option<[f32; 2]> {
+    // cursor position is relative to the client area, not to the window frame
+    let origin = viewport.window()?.inner_position().ok()?.cast::<f32>();
+    let position = position.cast::<f32>();
+    Some([position.x + origin.x, position.y + origin.y])
+}
+
+const DEFAULT_WHEEL_LINE_HEIGHT: f64 = 20.0;
+
+/// Adds winit scroll to imgui wheel, pixel deltas (touchpads) are converted to lines.
+pub fn handle_mouse_wheel(io: &mut Io, delta: MouseScrollDelta, line_height: f64) {
+    match delta {
+        MouseScrollDelta::LineDelta(h, v) => {
+            io.mouse_wheel_h += h;
+            io.mouse_wheel += v;
+        }
+        MouseScrollDelta::PixelDelta(pos) => {
+            let line_height = line_height.max(1.0);
+            io.mouse_wheel_h += (pos.x / line_height) as f32;
+            io.mouse_wheel += (pos.y / line_height) as f32;
+        }
+    }
+}
+
+/// Updates imgui key state and modifiers from winit keyboard input.
+pub fn handle_keyboard(io: &mut Io, input: &KeyboardInput) {
+    let key = match input.virtual_keycode {
+        Some(key) => key,
+        None => return,
     };
-    monitors.clear();
-    monitors.extend(
-        with_loop
-            .event_loop
-            .available_monitors()
-            .take(32)
-            .map(|monitor| {
-                let pos = monitor.position();
-                let posf = ImVec2 {
-                    x: pos.x as _,
-                    y: pos.y as _,
-                };
-                let size = monitor.size();
-                let sizef = ImVec2 {
-                    x: size.width as _,
-                    y: size.height as _,
-                };
+    let pressed = input.state == ElementState::Pressed;
+    io.keys_down[key as usize] = pressed;
+
+    // This is a bit redundant here, but we'll leave it in. The OS occasionally
+    // fails to send modifiers keys, but it doesn't seem to send false-positives,
+    // so double checking isn't terrible in case some system *doesn't* send
+    // device events sometimes.
+    match key {
+        VirtualKeyCode::LShift | VirtualKeyCode::RShift => io.key_shift = pressed,
+        VirtualKeyCode::LControl | VirtualKeyCode::RControl => io.key_ctrl = pressed,
+        VirtualKeyCode::LAlt | VirtualKeyCode::RAlt => io.key_alt = pressed,
+        VirtualKeyCode::LWin | VirtualKeyCode::RWin => io.key_super = pressed,
+        _ => (),
+    }
+}
+
+/// Feeds typed character into imgui text input.
+pub fn handle_character(io: &mut Io, ch: char) {
+    // Exclude the backspace key ('\u{7f}'). Otherwise we will insert this char and then
+    // delete it.
+    if ch != '\u{7f}' {
+        io.add_input_character(ch)
+    }
+}
+
+/// Looks up imgui viewport in the current context.
+///
+/// Pointer is owned by imgui and is valid until viewport is destroyed, don't keep the reference.
+unsafe fn viewport_by_key(key: Key) -> Option<&'static mut ImGuiViewport> {
+    let platform = imgui_sys::igGetPlatformIO().as_mut()?;
+    if platform.Viewports.Data.is_null() {
+        return None;
+    }
+    let viewports: &[*mut ImGuiViewport] =
+        std::slice::from_raw_parts(platform.Viewports.Data, platform.Viewports.Size as _);
+    viewports
+        .iter()
+        .filter_map(|vp| vp.as_mut())
+        .find(|vp| Key::from_ptr(vp.PlatformUserData) == key)
+}
+
+/// Updates imgui mouse button state, returns `false` if imgui has no slot for the button.
+///
+/// Extra buttons follow the middle one: `Other(1)` and `Other(2)` (X1 and X2 on Windows)
+/// take the last two of imgui's five slots.
+pub fn set_mouse_button(io: &mut Io, button: MouseButton, pressed: bool) -> bool {
+    let idx = match button {
+        MouseButton::Left => 0,
+        MouseButton::Right => 1,
+        MouseButton::Middle => 2,
+        MouseButton::Other(0) => return false,
+        MouseButton::Other(n) => 2 + n as usize,
+    };
+    match io.mouse_down.get_mut(idx) {
+        Some(down) => {
+            *down = pressed;
+            true
+        }
+        None => false,
+    }
+}
+
+fn to_winit_cursor(cursor: MouseCursor) -> CursorIcon {
+    match cursor {
+        MouseCursor::Arrow => CursorIcon::Default,
+        MouseCursor::TextInput => CursorIcon::Text,
+        MouseCursor::ResizeAll => CursorIcon::Move,
+        MouseCursor::ResizeNS => CursorIcon::NsResize,
+        MouseCursor::ResizeEW => CursorIcon::EwResize,
+        MouseCursor::ResizeNESW => CursorIcon::NeswResize,
+        MouseCursor::ResizeNWSE => CursorIcon::NwseResize,
+        MouseCursor::Hand => CursorIcon::Hand,
+        MouseCursor::NotAllowed => CursorIcon::NotAllowed,
+    }
+}
+
+/// Refreshes imgui monitor list, returns `true` if monitors were plugged, unplugged or reconfigured.
+fn update_monitors<M: crate::Manager, T, S>(
+    with_loop: &crate::WithLoop<M, T, S>,
+    platform: &mut ImGuiPlatformIO,
+) -> bool {
+    use imgui_sys::{ImGuiPlatformMonitor, ImVec2};
+    let current: Vec<ImGuiPlatformMonitor> = with_loop
+        .event_loop
+        .available_monitors()
+        .take(with_loop.monitor_limit().unwrap_or(usize::MAX))
+        .map(|monitor| {
+            let pos = monitor.position();
+            let posf = ImVec2 {
+                x: pos.x as _,
+                y: pos.y as _,
+            };
+            let size = monitor.size();
+            let sizef = ImVec2 {
+                x: size.width as _,
+                y: size.height as _,
+            };
+            let (work_posf, work_sizef) = match work_area(&monitor) {
+                Some((pos, size)) => (
+                    ImVec2 {
+                        x: pos.x as _,
+                        y: pos.y as _,
+                    },
+                    ImVec2 {
+                        x: size.width as _,
+                        y: size.height as _,
+                    },
+                ),
+                None => (posf, sizef),
+            };
+
+            ImGuiPlatformMonitor {
+                MainPos: posf,
+                MainSize: sizef,
+                WorkPos: work_posf,
+                WorkSize: work_sizef,
+                DpiScale: monitor.scale_factor() as _,
+            }
+        })
+        .collect();
 
-                ImGuiPlatformMonitor {
-                    MainPos: posf,
-                    MainSize: sizef,
-                    WorkPos: posf,
-                    WorkSize: sizef,
-                    DpiScale: monitor.scale_factor() as _,
-                }
-            }),
-    );
-    //let (ptr, length, capacity) = monitors.into_raw_parts();
-    //use std::convert::TryInto;
-    let (ptr, length, capacity) = (monitors.as_mut_ptr(), monitors.len(), monitors.capacity());
-    std::mem::forget(monitors);
     let raw = &mut platform.Monitors;
-    raw.Capacity = capacity as _;
-    raw.Size = length as _;
-    raw.Data = ptr;
+    let previous: &[ImGuiPlatformMonitor] = if raw.Data.is_null() {
+        &[]
+    } else {
+        unsafe { std::slice::from_raw_parts(raw.Data, raw.Size as usize) }
+    };
+    let unchanged = !raw.Data.is_null()
+        && previous.len() == current.len()
+        && previous
+            .iter()
+            .zip(&current)
+            .all(|(a, b)| same_monitor(a, b));
+    if unchanged {
+        return false;
+    }
+
+    unsafe { write_monitors(raw, &current) };
+    true
+}
+
+/// Copies `monitors` into imgui array, growing it with imgui allocator, which may be
+/// different from Rust one (`SetAllocatorFunctions`, `#[global_allocator]`).
+///
+/// # Safety
+/// `raw` must be either empty with null data or allocated by imgui.
+unsafe fn write_monitors(
+    raw: &mut imgui_sys::ImVector_ImGuiPlatformMonitor,
+    monitors: &[imgui_sys::ImGuiPlatformMonitor],
+) {
+    if monitors.is_empty() {
+        raw.Size = 0;
+        return;
+    }
+    if raw.Data.is_null() || (raw.Capacity as usize) < monitors.len() {
+        let data = imgui_sys::igMemAlloc(
+            monitors.len() * std::mem::size_of::<imgui_sys::ImGuiPlatformMonitor>(),
+        ) as *mut imgui_sys::ImGuiPlatformMonitor;
+        if !raw.Data.is_null() {
+            imgui_sys::igMemFree(raw.Data as _);
+        }
+        raw.Data = data;
+        raw.Capacity = monitors.len() as _;
+    }
+    std::ptr::copy_nonoverlapping(monitors.as_ptr(), raw.Data, monitors.len());
+    raw.Size = monitors.len() as _;
+}
+
+/// Monitor area without taskbar and docked toolbars.
+#[cfg(windows)]
+fn work_area(monitor: &MonitorHandle) -> Option<(PhysicalPosition<i32>, PhysicalSize<u32>)> {
+    use winapi::um::winuser::{GetMonitorInfoW, MONITORINFO};
+    use winit::platform::windows::MonitorHandleExtWindows;
+
+    let mut info: MONITORINFO = unsafe { std::mem::zeroed() };
+    info.cbSize = std::mem::size_of::<MONITORINFO>() as _;
+    if unsafe { GetMonitorInfoW(monitor.hmonitor() as _, &mut info) } == 0 {
+        return None;
+    }
+    let work = info.rcWork;
+    Some((
+        PhysicalPosition::new(work.left, work.top),
+        PhysicalSize::new(
+            (work.right - work.left) as u32,
+            (work.bottom - work.top) as u32,
+        ),
+    ))
+}
+
+/// winit has no work area query, imgui gets full monitor bounds.
+#[cfg(not(windows))]
+fn work_area(_monitor: &MonitorHandle) -> Option<(PhysicalPosition<i32>, PhysicalSize<u32>)> {
+    None
+}
+
+fn same_monitor(a: &imgui_sys::ImGuiPlatformMonitor, b: &imgui_sys::ImGuiPlatformMonitor) -> bool {
+    let same_vec = |a: imgui_sys::ImVec2, b: imgui_sys::ImVec2| a.x == b.x && a.y == b.y;
+    same_vec(a.MainPos, b.MainPos)
+        && same_vec(a.MainSize, b.MainSize)
+        && same_vec(a.WorkPos, b.WorkPos)
+        && same_vec(a.WorkSize, b.WorkSize)
+        && a.DpiScale == b.DpiScale
 }
 
 unsafe trait HasPlatformIO {
@@ -454,3 +1054,85 @@ unsafe trait HasPlatformIO {
     }
 }
 unsafe impl HasPlatformIO for imgui::Context {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use imgui_sys::{ImGuiPlatformMonitor, ImVec2, ImVector_ImGuiPlatformMonitor};
+
+    fn monitor(x: f32) -> ImGuiPlatformMonitor {
+        let pos = ImVec2 { x, y: 0.0 };
+        let size = ImVec2 {
+            x: 1920.0,
+            y: 1080.0,
+        };
+        ImGuiPlatformMonitor {
+            MainPos: pos,
+            MainSize: size,
+            WorkPos: pos,
+            WorkSize: size,
+            DpiScale: 1.0,
+        }
+    }
+
+    fn read(raw: &ImVector_ImGuiPlatformMonitor) -> &[ImGuiPlatformMonitor] {
+        unsafe { std::slice::from_raw_parts(raw.Data, raw.Size as usize) }
+    }
+
+    fn assert_same(raw: &ImVector_ImGuiPlatformMonitor, expected: &[ImGuiPlatformMonitor]) {
+        let actual = read(raw);
+        assert_eq!(actual.len(), expected.len());
+        assert!(actual.iter().zip(expected).all(|(a, b)| same_monitor(a, b)));
+    }
+
+    #[test]
+    fn monitors_round_trip_through_imgui_allocator() {
+        let _lock = crate::IMGUI_LOCK
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let mut raw: ImVector_ImGuiPlatformMonitor = unsafe { std::mem::zeroed() };
+
+        let two = [monitor(0.0), monitor(1920.0)];
+        unsafe { write_monitors(&mut raw, &two) };
+        assert_same(&raw, &two);
+
+        // grows into a new buffer
+        let three = [monitor(0.0), monitor(1920.0), monitor(3840.0)];
+        unsafe { write_monitors(&mut raw, &three) };
+        assert_same(&raw, &three);
+
+        // shrinks in place
+        let data = raw.Data;
+        let one = [monitor(-1920.0)];
+        unsafe { write_monitors(&mut raw, &one) };
+        assert_same(&raw, &one);
+        assert_eq!(raw.Data, data);
+        assert_eq!(raw.Capacity, 3);
+
+        unsafe { imgui_sys::igMemFree(raw.Data as _) };
+    }
+
+    #[test]
+    fn unregister_releases_proxy() {
+        let _lock = crate::IMGUI_LOCK
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let mut imgui = Context::create();
+        let wid = unsafe { WindowId::dummy() };
+        for _ in 0..3 {
+            let platform = Platform::init_window(&mut imgui, wid, PhysicalSize::new(800, 600));
+            assert_eq!(Rc::strong_count(&platform.proxy), 2);
+            assert!(imgui.io().backend_flags.contains(has_viewports()));
+
+            unregister_platform(&mut imgui);
+            assert_eq!(Rc::strong_count(&platform.proxy), 1);
+            assert!(!imgui.io().backend_flags.intersects(has_viewports()));
+            assert!(!imgui
+                .io()
+                .backend_flags
+                .contains(BackendFlags::HAS_MOUSE_CURSORS));
+        }
+        // second call is a no-op
+        unregister_platform(&mut imgui);
+    }
+}
@@ -2,7 +2,7 @@ use super::proxy::{Key, Proxy, SharedProxy};
 use crate::ViewportFlags;
 use imgui::sys as imgui_sys;
 use imgui_sys::{ImGuiPlatformIO, ImGuiViewport, ImVec2};
-use std::rc::Rc;
+use std::{ffi::c_void, mem::ManuallyDrop, ops::Deref, rc::Rc};
 
 pub(super) trait Callbacks {
     fn create_window(&mut self, flags: ViewportFlags) -> Key;
@@ -16,6 +16,31 @@ pub(super) trait Callbacks {
     fn get_focus(&self, key: Key) -> bool;
     fn get_minimized(&self, key: Key) -> bool;
     fn set_title(&mut self, key: Key, title: String);
+    fn set_alpha(&mut self, key: Key, alpha: f32);
+    fn set_ime_pos(&mut self, key: Key, pos: ImVec2);
+}
+
+/// Borrows proxy leaked into `BackendPlatformUserData` without touching its reference count,
+/// even if callback panics.
+struct ProxyGuard(ManuallyDrop<SharedProxy>);
+impl ProxyGuard {
+    unsafe fn from_io() -> Self {
+        let ptr = (*imgui_sys::igGetIO()).BackendPlatformUserData;
+        assert_eq!(ptr.is_null(), false);
+        Self(ManuallyDrop::new(Rc::from_raw(ptr as _)))
+    }
+}
+impl Drop for ProxyGuard {
+    fn drop(&mut self) {
+        let proxy = unsafe { ManuallyDrop::take(&mut self.0) };
+        let _ = Rc::into_raw(proxy);
+    }
+}
+impl Deref for ProxyGuard {
+    type Target = SharedProxy;
+    fn deref(&self) -> &SharedProxy {
+        &self.0
+    }
 }
 
 unsafe fn from_vp<R: 'static, F: FnOnce(&mut Proxy, &mut Key) -> R>(
@@ -23,24 +48,21 @@ unsafe fn from_vp<R: 'static, F: FnOnce(&mut Proxy, &mut Key) -> R>(
     callback: F,
 ) -> R {
     let vp = &mut (*vp);
-    let ptr = (*imgui_sys::igGetIO()).BackendPlatformUserData;
-    assert_eq!(ptr.is_null(), false);
-    let proxy: SharedProxy = Rc::from_raw(ptr as _);
-    let ret = {
-        let mut guard = proxy.borrow_mut();
-        let key: &mut Key = std::mem::transmute(&mut vp.PlatformUserData);
-        callback(&mut *guard, key)
-    };
-    std::mem::forget(proxy);
+    let proxy = ProxyGuard::from_io();
+    let mut guard = proxy.borrow_mut();
+    let mut key = Key::from_ptr(vp.PlatformUserData);
+    let ret = callback(&mut *guard, &mut key);
+    vp.PlatformUserData = key.into_ptr();
     ret
 }
 
 pub fn register_platform_callbacks(platform: &mut ImGuiPlatformIO) {
     unsafe extern "C" fn create_window(vp: *mut ImGuiViewport) {
         from_vp(vp, |proxy, key| {
-            assert_eq!(*key, 0);
+            assert!(key.is_unset());
             let flags = (*vp).Flags as u32;
             *key = proxy.create_window(ViewportFlags::from_bits_unchecked(flags));
+            proxy.bind_viewport(*key, vp);
             //dbg!(key);
             //dbg!((*vp).PlatformUserData);
         });
@@ -50,7 +72,8 @@ pub fn register_platform_callbacks(platform: &mut ImGuiPlatformIO) {
     unsafe extern "C" fn destroy_window(vp: *mut ImGuiViewport) {
         from_vp(vp, |proxy, key| {
             proxy.destroy_window(*key);
-            *key = 0;
+            proxy.unbind_viewport(*key);
+            *key = Key::UNSET;
         });
     }
     platform.Platform_DestroyWindow = Some(destroy_window);
@@ -69,14 +92,21 @@ pub fn register_platform_callbacks(platform: &mut ImGuiPlatformIO) {
     }
     platform.Platform_SetWindowPos = Some(set_window_pos);
 
-    unsafe extern "C" fn get_window_pos(vp: *mut ImGuiViewport, pos: *mut ImVec2) {
-        /*if (*vp).PlatformUserData as usize > 1 {
-            println!("get_window_pos!!!");
-        }*/
-        *pos = from_vp(vp, |proxy, key| proxy.get_position(*key));
+    #[cfg(target_env = "msvc")]
+    {
+        unsafe extern "C" fn get_window_pos(vp: *mut ImGuiViewport, pos: *mut ImVec2) {
+            *pos = from_vp(vp, |proxy, key| proxy.get_position(*key));
+        }
+        unsafe {
+            ImGuiPlatformIO_Set_Platform_GetWindowPos(platform, get_window_pos);
+        }
     }
-    unsafe {
-        ImGuiPlatformIO_Set_Platform_GetWindowPos(platform, get_window_pos);
+    #[cfg(not(target_env = "msvc"))]
+    {
+        unsafe extern "C" fn get_window_pos(vp: *mut ImGuiViewport) -> ImVec2 {
+            from_vp(vp, |proxy, key| proxy.get_position(*key))
+        }
+        platform.Platform_GetWindowPos = Some(get_window_pos);
     }
 
     unsafe extern "C" fn set_window_size(vp: *mut ImGuiViewport, size: ImVec2) {
@@ -86,11 +116,21 @@ pub fn register_platform_callbacks(platform: &mut ImGuiPlatformIO) {
     }
     platform.Platform_SetWindowSize = Some(set_window_size);
 
-    unsafe extern "C" fn get_window_size(vp: *mut ImGuiViewport, size: *mut ImVec2) {
-        *size = from_vp(vp, |proxy, key| proxy.get_size(*key));
+    #[cfg(target_env = "msvc")]
+    {
+        unsafe extern "C" fn get_window_size(vp: *mut ImGuiViewport, size: *mut ImVec2) {
+            *size = from_vp(vp, |proxy, key| proxy.get_size(*key));
+        }
+        unsafe {
+            ImGuiPlatformIO_Set_Platform_GetWindowSize(platform, get_window_size);
+        }
     }
-    unsafe {
-        ImGuiPlatformIO_Set_Platform_GetWindowSize(platform, get_window_size);
+    #[cfg(not(target_env = "msvc"))]
+    {
+        unsafe extern "C" fn get_window_size(vp: *mut ImGuiViewport) -> ImVec2 {
+            from_vp(vp, |proxy, key| proxy.get_size(*key))
+        }
+        platform.Platform_GetWindowSize = Some(get_window_size);
     }
 
     unsafe extern "C" fn set_window_focus(vp: *mut ImGuiViewport) {
@@ -116,13 +156,111 @@ pub fn register_platform_callbacks(platform: &mut ImGuiPlatformIO) {
     ) {
         let title = std::ffi::CStr::from_ptr(str).to_bytes();
         from_vp(vp, |proxy, key| {
-            proxy.set_title(*key, std::str::from_utf8(title).unwrap().to_owned());
+            proxy.set_title(*key, String::from_utf8_lossy(title).into_owned());
         });
     }
     platform.Platform_SetWindowTitle = Some(set_window_title);
+
+    unsafe extern "C" fn set_window_alpha(vp: *mut ImGuiViewport, alpha: f32) {
+        from_vp(vp, |proxy, key| {
+            proxy.set_alpha(*key, alpha);
+        });
+    }
+    platform.Platform_SetWindowAlpha = Some(set_window_alpha);
+
+    // called by imgui only when caret of focused text field moves, with viewport owning the field
+    unsafe extern "C" fn set_ime_input_pos(vp: *mut ImGuiViewport, pos: ImVec2) {
+        from_vp(vp, |proxy, key| {
+            proxy.set_ime_pos(*key, pos);
+        });
+    }
+    platform.Platform_SetImeInputPos = Some(set_ime_input_pos);
+
+    // called every frame after imgui applied its own geometry changes
+    unsafe extern "C" fn update_window(vp: *mut ImGuiViewport) {
+        let (pos, size) = ((*vp).Pos, (*vp).Size);
+        let (request_move, request_resize) =
+            from_vp(vp, |proxy, key| proxy.update_window(*key, pos, size));
+        if request_move {
+            (*vp).PlatformRequestMove = true;
+        }
+        if request_resize {
+            (*vp).PlatformRequestResize = true;
+        }
+    }
+    platform.Platform_UpdateWindow = Some(update_window);
+}
+
+pub fn unregister_platform_callbacks(platform: &mut ImGuiPlatformIO) {
+    platform.Platform_CreateWindow = None;
+    platform.Platform_DestroyWindow = None;
+    platform.Platform_ShowWindow = None;
+    platform.Platform_SetWindowPos = None;
+    platform.Platform_GetWindowPos = None;
+    platform.Platform_SetWindowSize = None;
+    platform.Platform_GetWindowSize = None;
+    platform.Platform_SetWindowFocus = None;
+    platform.Platform_SetImeInputPos = None;
+    platform.Platform_UpdateWindow = None;
+    platform.Platform_GetWindowFocus = None;
+    platform.Platform_GetWindowMinimized = None;
+    platform.Platform_SetWindowTitle = None;
+    platform.Platform_SetWindowAlpha = None;
+}
+
+/// State handed to `Renderer_RenderWindow` through the renderer argument
+/// of `igRenderPlatformWindowsDefault`.
+pub(super) struct RenderArg<'a, M: crate::Manager> {
+    pub proxy: &'a Proxy,
+    pub manager: &'a mut M,
+    pub renderer: &'a mut M::Renderer,
+    pub rendered: usize,
+}
+
+/// Installs `Renderer_RenderWindow` for manager type `M`.
+///
+/// `Renderer_CreateWindow`, `Renderer_DestroyWindow` and `Renderer_SetWindowSize` stay unset:
+/// surfaces are owned by viewports and follow their windows,
+/// `Renderer_SwapBuffers` too, as `Viewport::on_draw` presents the frame itself.
+pub(super) fn register_renderer_callbacks<M: crate::Manager>(platform: &mut ImGuiPlatformIO) {
+    unsafe extern "C" fn render_window<M: crate::Manager>(
+        vp: *mut ImGuiViewport,
+        arg: *mut c_void,
+    ) {
+        use imgui::internal::RawCast;
+        let (vp, arg) = match (vp.as_ref(), (arg as *mut RenderArg<M>).as_mut()) {
+            (Some(vp), Some(arg)) => (vp, arg),
+            _ => return,
+        };
+        let key = Key::from_ptr(vp.PlatformUserData);
+        if key.is_unset() {
+            return;
+        }
+        let wid = match arg.proxy.cache(key) {
+            Some(cache) if !cache.minimized => cache.wid,
+            _ => return,
+        };
+        let draw_data = match vp.DrawData.as_ref() {
+            Some(draw_data) if draw_data.Valid => draw_data,
+            _ => return,
+        };
+        if let Some(viewport) = arg.manager.viewport_mut(wid) {
+            viewport.on_draw(arg.renderer, RawCast::from_raw(draw_data));
+            arg.rendered += 1;
+        }
+    }
+    platform.Renderer_RenderWindow = Some(render_window::<M>);
+}
+
+pub(super) fn unregister_renderer_callbacks(platform: &mut ImGuiPlatformIO) {
+    platform.Renderer_RenderWindow = None;
 }
 
+/// MSVC returns `ImVec2` (a C++ class with constructors) through hidden pointer,
+/// unlike `extern "C"` functions in Rust, so getters go through cimgui shims there.
+#[cfg(target_env = "msvc")]
 type PlatformUserCallback = unsafe extern "C" fn(*mut ImGuiViewport, *mut ImVec2);
+#[cfg(target_env = "msvc")]
 extern "C" {
     //void ImGuiPlatformIO_Set_Platform_GetWindowPos(ImGuiPlatformIO* platform_io, void(*user_callback)(ImGuiViewport* vp, ImVec2* out_pos))
     fn ImGuiPlatformIO_Set_Platform_GetWindowPos(
@@ -0,0 +1,31 @@
+use copypasta::{ClipboardContext, ClipboardProvider};
+use imgui::{ClipboardBackend, ImStr, ImString};
+
+struct Clipboard(ClipboardContext);
+
+impl ClipboardBackend for Clipboard {
+    fn get(&mut self) -> Option<ImString> {
+        self.0.get_contents().ok().map(ImString::new)
+    }
+    fn set(&mut self, value: &ImStr) {
+        // failing to copy shouldn't crash ui
+        let _ = self.0.set_contents(value.to_str().to_owned());
+    }
+}
+
+/// Connects imgui copy/paste to the system clipboard, returns `false` if clipboard is unavailable.
+///
+/// imgui keeps an owned copy of the last text returned from `get`, so the pointer it hands out
+/// stays valid until the next call, even if `set` was called in between.
+pub fn register_clipboard(imgui: &mut imgui::Context) -> bool {
+    match ClipboardContext::new() {
+        Ok(context) => {
+            imgui.set_clipboard_backend(Box::new(Clipboard(context)));
+            true
+        }
+        Err(err) => {
+            eprintln!("clipboard is unavailable: {}", err);
+            false
+        }
+    }
+}
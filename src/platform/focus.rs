@@ -0,0 +1,96 @@
+use super::proxy::Key;
+use imgui::sys as imgui_sys;
+use imgui_sys::ImGuiWindow;
+
+/// imgui windows that own a viewport, from back to front.
+///
+/// Borrows the context mutably, so imgui can't start a new frame and reorder
+/// or free windows while the iterator is alive.
+pub struct FocusOrder<'a> {
+    windows: std::slice::Iter<'a, *mut ImGuiWindow>,
+}
+
+impl<'a> FocusOrder<'a> {
+    fn next_window(&mut self) -> Option<&'a ImGuiWindow> {
+        for &window in &mut self.windows {
+            match unsafe { window.as_ref() } {
+                Some(window) if window.ViewportOwned && !window.Viewport.is_null() => {
+                    return Some(window)
+                }
+                _ => continue,
+            }
+        }
+        None
+    }
+    /// One entry per OS window: child, popup and tooltip windows are skipped and every
+    /// viewport is reported once, at the position of its front-most window.
+    pub fn top_level(mut self) -> TopLevelFocusOrder<'a> {
+        const NOT_TOP_LEVEL: i32 = (imgui_sys::ImGuiWindowFlags_ChildWindow
+            | imgui_sys::ImGuiWindowFlags_Popup
+            | imgui_sys::ImGuiWindowFlags_Tooltip) as i32;
+        let mut windows: Vec<&'a ImGuiWindow> = vec![];
+        while let Some(window) = self.next_window() {
+            if window.Flags & NOT_TOP_LEVEL != 0 {
+                continue;
+            }
+            windows.retain(|other| other.ViewportId != window.ViewportId);
+            windows.push(window);
+        }
+        TopLevelFocusOrder {
+            windows: windows.into_iter(),
+        }
+    }
+}
+
+impl<'a> Iterator for FocusOrder<'a> {
+    type Item = (&'a str, Key);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_window().map(entry)
+    }
+}
+
+/// See `FocusOrder::top_level`.
+pub struct TopLevelFocusOrder<'a> {
+    windows: std::vec::IntoIter<&'a ImGuiWindow>,
+}
+
+impl<'a> Iterator for TopLevelFocusOrder<'a> {
+    type Item = (&'a str, Key);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.windows.next().map(entry)
+    }
+}
+
+/// Window name, empty if it isn't valid UTF-8.
+fn entry(window: &ImGuiWindow) -> (&str, Key) {
+    let name = unsafe { std::ffi::CStr::from_ptr(window.Name) }
+        .to_str()
+        .unwrap_or("");
+    let key = unsafe { Key::from_ptr((*window.Viewport).PlatformUserData) };
+    (name, key)
+}
+
+pub fn focus_order(_context: &mut imgui::Context) -> FocusOrder<'_> {
+    let windows: &[*mut ImGuiWindow] = unsafe {
+        match imgui_sys::igGetCurrentContext().as_ref() {
+            Some(context) if !context.WindowsFocusOrder.Data.is_null() => {
+                std::slice::from_raw_parts(
+                    context.WindowsFocusOrder.Data,
+                    context.WindowsFocusOrder.Size as usize,
+                )
+            }
+            _ => &[],
+        }
+    };
+    FocusOrder {
+        windows: windows.iter(),
+    }
+}
+
+/// Keys of windows with their own viewport, the focused one is the last.
+pub fn focused_viewport_keys(context: &mut imgui::Context) -> Vec<Key> {
+    focus_order(context)
+        .map(|(_, key)| key)
+        .filter(|key| !key.is_unset())
+        .collect()
+}
@@ -1,22 +1,75 @@
-use imgui::sys::ImVec2;
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use imgui::sys::{ImGuiViewport, ImVec2};
+use std::{cell::RefCell, collections::HashMap, ffi::c_void, fmt, rc::Rc};
 use winit::{
-    dpi::{PhysicalPosition, PhysicalSize},
+    dpi::{PhysicalPosition, PhysicalSize, Size},
     window::WindowId,
 };
 
-use crate::{Manager, Viewport, ViewportFlags, WindowSpawner, WithLoop};
+use crate::{Manager, ManagerError, Viewport, ViewportFlags, WindowSpawner, WithLoop};
 
-pub(super) type Key = usize;
+/// Identifies imgui viewport, stored in its `PlatformUserData`.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key(usize);
+impl Key {
+    /// Value of `PlatformUserData` for viewports without a window.
+    pub const UNSET: Key = Key(0);
+
+    pub fn from_ptr(ptr: *mut c_void) -> Self {
+        Key(ptr as usize)
+    }
+    pub fn into_ptr(self) -> *mut c_void {
+        self.0 as *mut c_void
+    }
+    pub fn is_unset(self) -> bool {
+        self == Self::UNSET
+    }
+}
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
 pub(super) type SharedProxy = Rc<RefCell<Proxy>>;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyError {
+    /// No window was created for this key, or it was already destroyed.
+    UnknownKey(Key),
+    /// Window is known to proxy, but manager has no viewport for it.
+    WindowGone(WindowId),
+    /// Manager refused to add spawned window.
+    SpawnFailed(ManagerError),
+}
+impl fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProxyError::UnknownKey(key) => write!(f, "unknown viewport key {}", key),
+            ProxyError::WindowGone(wid) => write!(f, "window {:?} is already removed", wid),
+            ProxyError::SpawnFailed(err) => write!(f, "failed to spawn window: {}", err),
+        }
+    }
+}
+impl std::error::Error for ProxyError {}
+
 #[derive(Debug)]
 pub struct Cache {
     pub(super) wid: WindowId,
     pub(super) minimized: bool,
     pub(super) focus: bool,
+    pub(super) top_most: bool,
     pub(super) size: Option<ImVec2>,
     pub(super) pos: Option<ImVec2>,
+    /// Geometry requested by imgui, winit echoes it back with `Moved`/`Resized` events,
+    /// possibly with intermediate values first. Kept until the value arrives or the next frame.
+    expected_pos: Option<PhysicalPosition<i32>>,
+    expected_size: Option<PhysicalSize<u32>>,
+    min_size: Option<Size>,
+    max_size: Option<Size>,
+    /// Position or size was applied, window can be shown without flashing at default place.
+    placed: bool,
+    /// `ShowWindow` came before window was placed.
+    show_pending: bool,
 }
 impl Cache {
     fn new(wid: WindowId) -> Self {
@@ -24,10 +77,67 @@ impl Cache {
             wid,
             minimized: false,
             focus: true,
+            top_most: false,
             size: None,
             pos: None,
+            expected_pos: None,
+            expected_size: None,
+            min_size: None,
+            max_size: None,
+            placed: false,
+            show_pending: false,
+        }
+    }
+    /// Clamps size requested by imgui into window size constraints.
+    fn constrain(&self, size: PhysicalSize<u32>, scale_factor: f64) -> PhysicalSize<u32> {
+        let mut size = size;
+        if let Some(min) = self
+            .min_size
+            .map(|min| min.to_physical::<u32>(scale_factor))
+        {
+            size.width = size.width.max(min.width);
+            size.height = size.height.max(min.height);
+        }
+        if let Some(max) = self
+            .max_size
+            .map(|max| max.to_physical::<u32>(scale_factor))
+        {
+            size.width = size.width.min(max.width);
+            size.height = size.height.min(max.height);
+        }
+        size
+    }
+    /// Checks if reported position is the echo of imgui request rather than user action,
+    /// consumes the stamp once requested position is reached.
+    pub(super) fn is_pos_echo(&mut self, pos: PhysicalPosition<i32>) -> bool {
+        match self.expected_pos {
+            Some(expected) => {
+                if expected == pos {
+                    self.expected_pos = None;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+    /// Checks if reported size is the echo of imgui request rather than user action,
+    /// consumes the stamp once requested size is reached.
+    pub(super) fn is_size_echo(&mut self, size: PhysicalSize<u32>) -> bool {
+        match self.expected_size {
+            Some(expected) => {
+                if expected == size {
+                    self.expected_size = None;
+                }
+                true
+            }
+            None => false,
         }
     }
+    /// Drops stamps whose echo never came, e.g. when OS adjusted requested geometry.
+    fn expire_echo(&mut self) {
+        self.expected_pos = None;
+        self.expected_size = None;
+    }
     pub(super) fn set_size(&mut self, size: PhysicalSize<u32>) {
         self.size = Some(ImVec2 {
             x: size.width as _,
@@ -56,6 +166,79 @@ enum Kind {
     SetSize(ImVec2),
     SetFocus,
     SetTitle(String),
+    SetAlpha(f32),
+    SetTopMost(bool),
+    SetImePos(ImVec2),
+    SetSizeConstraints(Option<Size>, Option<Size>),
+}
+
+/// Drops commands made pointless by a destroy later in the same batch.
+///
+/// Mutations of destroyed window are skipped, window created and destroyed within
+/// the batch is never spawned at all.
+fn coalesce(commands: Vec<Command>) -> Vec<Command> {
+    // destroyed key -> whether it was created in this batch
+    let mut destroyed: HashMap<Key, bool> = commands
+        .iter()
+        .filter(|command| matches!(command.kind, Kind::DestroyWindow))
+        .map(|command| (command.key, false))
+        .collect();
+    if destroyed.is_empty() {
+        return commands;
+    }
+    for command in &commands {
+        if let Kind::CreateWindow { .. } = command.kind {
+            if let Some(created) = destroyed.get_mut(&command.key) {
+                *created = true;
+            }
+        }
+    }
+    commands
+        .into_iter()
+        .filter(|command| match destroyed.get(&command.key) {
+            None => true,
+            Some(&created) => !created && matches!(command.kind, Kind::DestroyWindow),
+        })
+        .collect()
+}
+
+/// Moves `ShowWindow` of windows created in the batch after their last `SetPos`/`SetSize`,
+/// so they never appear before being placed, whatever order imgui emitted them in.
+fn place_before_show(commands: Vec<Command>) -> Vec<Command> {
+    let created: Vec<Key> = commands
+        .iter()
+        .filter(|command| matches!(command.kind, Kind::CreateWindow { .. }))
+        .map(|command| command.key)
+        .collect();
+    if created.is_empty() {
+        return commands;
+    }
+    // created key -> index of its last placement
+    let mut last_placed: HashMap<Key, usize> = HashMap::new();
+    for (index, command) in commands.iter().enumerate() {
+        if created.contains(&command.key)
+            && matches!(command.kind, Kind::SetPos(_) | Kind::SetSize(_))
+        {
+            last_placed.insert(command.key, index);
+        }
+    }
+    let mut deferred: HashMap<Key, Command> = HashMap::new();
+    let mut ordered = Vec::with_capacity(commands.len());
+    for (index, command) in commands.into_iter().enumerate() {
+        let key = command.key;
+        match last_placed.get(&key) {
+            Some(&last) if index < last && matches!(command.kind, Kind::ShowWindow) => {
+                deferred.insert(key, command);
+                continue;
+            }
+            Some(&last) if index == last => {
+                ordered.push(command);
+                ordered.extend(deferred.remove(&key));
+            }
+            _ => ordered.push(command),
+        }
+    }
+    ordered
 }
 
 #[derive(Debug)]
@@ -63,6 +246,13 @@ pub(super) struct Proxy {
     caches: HashMap<Key, Cache>,
     commands: Vec<Command>,
     next_id: Key,
+    /// imgui viewports, recorded when key is written into their `PlatformUserData`.
+    viewport_ptrs: HashMap<Key, *mut ImGuiViewport>,
+    /// Caches of windows the manager lost, kept only to answer imgui queries until it destroys them.
+    gone: HashMap<Key, Cache>,
+    /// Size constraints of spawned windows.
+    default_min_size: Option<Size>,
+    default_max_size: Option<Size>,
 }
 
 impl Proxy {
@@ -70,7 +260,11 @@ impl Proxy {
         Self {
             caches: HashMap::new(),
             commands: vec![],
-            next_id: 1,
+            next_id: Key(1),
+            viewport_ptrs: HashMap::new(),
+            gone: HashMap::new(),
+            default_min_size: None,
+            default_max_size: None,
         }
     }
     pub(super) fn shared() -> SharedProxy {
@@ -82,68 +276,277 @@ impl Proxy {
         self.caches.insert(key, cache);
         key
     }
+    /// Applies queued commands and refreshes caches.
+    ///
+    /// Commands that can't be applied are skipped, the first error is returned after all of them were processed.
     pub(super) fn update<M: Manager, T, S: WindowSpawner<M::Viewport>>(
         &mut self,
         manager: &mut WithLoop<'_, M, T, S>,
-    ) {
-        /*if !self.commands.is_empty() {
-            dbg!(&self.commands);
-        }*/
-        for Command { key, kind } in self.commands.drain(..) {
-            match &kind {
-                Kind::CreateWindow { flags } => {
-                    let wid = manager.spawn_window(*flags);
-                    let cache = Cache::new(wid);
-                    self.caches.insert(key, cache);
+    ) -> Result<(), ProxyError> {
+        let mut result = Ok(());
+        // stamps of the previous frame had their chance
+        for cache in self.caches.values_mut() {
+            cache.expire_echo();
+        }
+        let commands = place_before_show(coalesce(std::mem::take(&mut self.commands)));
+        for command in commands {
+            let applied = self.apply(manager, command);
+            result = result.and(applied);
+        }
+        let mut gone = vec![];
+        for (&key, cache) in self.caches.iter_mut() {
+            let wid = cache.wid;
+            let viewport = match manager.manager.viewport_mut(wid) {
+                Some(viewport) => viewport,
+                None => {
+                    result = result.and(Err(ProxyError::WindowGone(wid)));
+                    gone.push(key);
+                    continue;
                 }
-                Kind::DestroyWindow => {
-                    let wid = self.caches.remove(&key).unwrap().wid;
-                    manager.destroy(wid);
+            };
+            // imgui didn't place the window this time, don't keep it hidden
+            if cache.show_pending {
+                cache.show_pending = false;
+                manager.spawner.show_window(viewport);
+            }
+            match viewport.window() {
+                Some(window) if !cache.minimized => {
+                    cache.set_size(window.inner_size());
+                    // not supported on some platforms (Wayland), keep the last known position
+                    if let Ok(pos) = window.outer_position() {
+                        cache.set_pos(pos);
+                    }
                 }
-                _ => {
-                    let wid = self.caches.get(&key).unwrap().wid;
-                    let viewport = manager.manager.viewport_mut(wid).expect("Expect viewport");
-                    match kind {
-                        Kind::CreateWindow { .. } | Kind::DestroyWindow => unreachable!(),
-                        Kind::ShowWindow => {
-                            manager.spawner.show_window(viewport);
+                _ => {}
+            }
+        }
+        // report lost window once, imgui is asked to close its viewport
+        for key in gone {
+            if let Some(mut cache) = self.caches.remove(&key) {
+                cache.minimized = true;
+                self.gone.insert(key, cache);
+            }
+            if let Some(vp) = self.viewport_ptrs.remove(&key) {
+                unsafe {
+                    (*vp).PlatformRequestClose = true;
+                }
+            }
+        }
+        result
+    }
+    fn apply<M: Manager, T, S: WindowSpawner<M::Viewport>>(
+        &mut self,
+        manager: &mut WithLoop<'_, M, T, S>,
+        Command { key, kind }: Command,
+    ) -> Result<(), ProxyError> {
+        match &kind {
+            Kind::CreateWindow { flags } => {
+                let wid = manager
+                    .spawn_window(*flags)
+                    .map_err(ProxyError::SpawnFailed)?;
+                let mut cache = Cache::new(wid);
+                cache.top_most = flags.contains(ViewportFlags::TOPMOST);
+                cache.min_size = self.default_min_size;
+                cache.max_size = self.default_max_size;
+                if let Some(window) = manager.viewport(wid).and_then(|viewport| viewport.window()) {
+                    window.set_min_inner_size(cache.min_size);
+                    window.set_max_inner_size(cache.max_size);
+                }
+                self.caches.insert(key, cache);
+            }
+            Kind::DestroyWindow => {
+                if self.gone.remove(&key).is_some() {
+                    // already reported as `WindowGone`
+                    return Ok(());
+                }
+                let wid = self
+                    .caches
+                    .remove(&key)
+                    .ok_or(ProxyError::UnknownKey(key))?
+                    .wid;
+                if manager.viewport(wid).is_none() {
+                    return Err(ProxyError::WindowGone(wid));
+                }
+                manager.destroy(wid);
+            }
+            _ if self.gone.contains_key(&key) => {}
+            _ => {
+                let wid = self.cache(key).ok_or(ProxyError::UnknownKey(key))?.wid;
+                let viewport = manager
+                    .manager
+                    .viewport_mut(wid)
+                    .ok_or(ProxyError::WindowGone(wid))?;
+                match kind {
+                    Kind::CreateWindow { .. } | Kind::DestroyWindow => unreachable!(),
+                    Kind::ShowWindow => match self.caches.get_mut(&key) {
+                        // spawned windows are hidden until placed
+                        Some(cache) if !cache.placed => cache.show_pending = true,
+                        _ => manager.spawner.show_window(viewport),
+                    },
+                    Kind::SetPos(pos) => {
+                        let pos = winit::dpi::PhysicalPosition {
+                            x: pos.x.round() as i32,
+                            y: pos.y.round() as i32,
+                        };
+                        let window = viewport.window();
+                        if let Some(window) = window {
+                            window.set_outer_position(pos);
                         }
-                        Kind::SetPos(pos) => {
-                            let pos = winit::dpi::PhysicalPosition {
-                                x: pos.x.round() as i32,
-                                y: pos.y.round() as i32,
-                            };
-                            viewport.window().set_outer_position(pos);
+                        if let Some(cache) = self.caches.get_mut(&key) {
+                            cache.set_pos(pos);
+                            if window.is_some() {
+                                cache.expected_pos = Some(pos);
+                            }
+                            cache.placed = true;
+                            if std::mem::take(&mut cache.show_pending) {
+                                manager.spawner.show_window(viewport);
+                            }
                         }
-                        Kind::SetSize(size) => {
-                            let size = winit::dpi::PhysicalSize {
-                                width: size.x.round() as u32,
-                                height: size.y.round() as u32,
+                    }
+                    Kind::SetSize(size) => {
+                        let size = winit::dpi::PhysicalSize {
+                            width: size.x.round() as u32,
+                            height: size.y.round() as u32,
+                        };
+                        if let Some(cache) = self.caches.get_mut(&key) {
+                            let size = cache.constrain(size, viewport.scale_factor());
+                            let has_window = match viewport.window() {
+                                Some(window) => {
+                                    window.set_inner_size(size);
+                                    true
+                                }
+                                None => false,
                             };
-                            viewport.window().set_inner_size(size);
                             viewport.on_resize();
+                            cache.set_size(size);
+                            if has_window {
+                                cache.expected_size = Some(size);
+                            }
+                            cache.placed = true;
+                            if std::mem::take(&mut cache.show_pending) {
+                                manager.spawner.show_window(viewport);
+                            }
+                        }
+                    }
+                    Kind::SetSizeConstraints(min, max) => {
+                        if let Some(window) = viewport.window() {
+                            window.set_min_inner_size(min);
+                            window.set_max_inner_size(max);
                         }
-                        Kind::SetFocus => {
-                            //unimplemented!();
+                        if let Some(cache) = self.caches.get_mut(&key) {
+                            cache.min_size = min;
+                            cache.max_size = max;
+                        }
+                    }
+                    Kind::SetFocus => {
+                        manager.spawner.focus_window(viewport);
+                        // winit will send Focused events later, but imgui can ask for focus before them
+                        for (&other, cache) in &mut self.caches {
+                            cache.focus = other == key;
+                        }
+                    }
+                    Kind::SetTitle(title) => {
+                        if let Some(window) = viewport.window() {
+                            window.set_title(&title);
+                        }
+                    }
+                    Kind::SetAlpha(alpha) => {
+                        manager.spawner.set_window_alpha(viewport, alpha);
+                    }
+                    Kind::SetTopMost(top_most) => {
+                        if let Some(window) = viewport.window() {
+                            window.set_always_on_top(top_most);
+                        }
+                    }
+                    Kind::SetImePos(pos) => {
+                        let window = match viewport.window() {
+                            Some(window) => window,
+                            None => return Ok(()),
+                        };
+                        // winit expects position relative to the client area
+                        if let Ok(origin) = window.inner_position() {
+                            window.set_ime_position(winit::dpi::PhysicalPosition {
+                                x: pos.x as i32 - origin.x,
+                                y: pos.y as i32 - origin.y,
+                            });
                         }
-                        Kind::SetTitle(title) => viewport.window().set_title(&title),
                     }
                 }
             }
         }
-        for (_key, cache) in &mut self.caches {
-            let wid = cache.wid;
-            let viewport = manager.viewport_mut(wid).expect("Expect viewport");
-            let window = viewport.window();
-            if !cache.minimized {
-                cache.set_size(window.inner_size());
-                cache.set_pos(window.outer_position().unwrap());
-            }
+        Ok(())
+    }
+    pub(super) fn window_id(&self, key: Key) -> Option<WindowId> {
+        self.cache(key).map(|cache| cache.wid)
+    }
+    pub(super) fn key_for_window(&self, wid: WindowId) -> Option<Key> {
+        self.caches
+            .iter()
+            .find(|(_, cache)| cache.wid == wid)
+            .map(|(&key, _)| key)
+    }
+    /// imgui has no callback for flag changes, so platform compares flags of existing viewports every frame.
+    pub(super) fn sync_top_most(&mut self, key: Key, top_most: bool) {
+        match self.cache_mut(key) {
+            Some(cache) if cache.top_most != top_most => cache.top_most = top_most,
+            _ => return,
         }
+        self.commands.push(Command {
+            key,
+            kind: Kind::SetTopMost(top_most),
+        });
+    }
+    /// Compares window geometry known from OS with imgui's one, returns whether imgui should
+    /// take position and size from the window.
+    ///
+    /// Requests made by imgui and not yet applied to the window are not treated as divergence.
+    pub(super) fn update_window(&self, key: Key, pos: ImVec2, size: ImVec2) -> (bool, bool) {
+        let cache = match self.cache(key) {
+            Some(cache) if !cache.minimized => cache,
+            _ => return (false, false),
+        };
+        let diverged = |cached: Option<ImVec2>, expected: ImVec2| {
+            cached.map_or(false, |cached| {
+                (cached.x - expected.x).abs() >= 1.0 || (cached.y - expected.y).abs() >= 1.0
+            })
+        };
+        (
+            cache.expected_pos.is_none() && diverged(cache.pos, pos),
+            cache.expected_size.is_none() && diverged(cache.size, size),
+        )
+    }
+    pub(super) fn bind_viewport(&mut self, key: Key, vp: *mut ImGuiViewport) {
+        self.viewport_ptrs.insert(key, vp);
+    }
+    pub(super) fn unbind_viewport(&mut self, key: Key) {
+        self.viewport_ptrs.remove(&key);
+    }
+    pub(super) fn viewport_ptr_for_window(&self, wid: WindowId) -> Option<*mut ImGuiViewport> {
+        let key = self.key_for_window(wid)?;
+        self.viewport_ptrs.get(&key).copied()
+    }
+    pub(super) fn try_get_focus(&self, key: Key) -> Option<bool> {
+        self.cache(key).map(|cache| cache.focus)
+    }
+    pub(super) fn try_get_position(&self, key: Key) -> Option<ImVec2> {
+        self.cache(key)?.pos
+    }
+    pub(super) fn try_get_size(&self, key: Key) -> Option<ImVec2> {
+        self.cache(key)?.size
+    }
+    pub(super) fn set_size_constraints(&mut self, key: Key, min: Option<Size>, max: Option<Size>) {
+        self.commands.push(Command {
+            key,
+            kind: Kind::SetSizeConstraints(min, max),
+        });
+    }
+    pub(super) fn set_default_size_constraints(&mut self, min: Option<Size>, max: Option<Size>) {
+        self.default_min_size = min;
+        self.default_max_size = max;
     }
     fn next_key(&mut self) -> Key {
         let key = self.next_id;
-        self.next_id += 1;
+        self.next_id.0 += 1;
         key
     }
     /*pub fn draw_data<F>(
@@ -168,7 +571,7 @@ impl Proxy {
                 if vp.DrawData.is_null() || vp.PlatformUserData.is_null() {
                     continue;
                 }
-                let key: Key = std::mem::transmute(vp.PlatformUserData);
+                let key = Key::from_ptr(vp.PlatformUserData);
                 let cache = self.windows.get(&key).unwrap();
                 let window = windows.get_mut(&cache.wid).unwrap();
                 let draw_data = RawCast::from_raw(&*vp.DrawData);
@@ -179,20 +582,22 @@ impl Proxy {
 
     #[track_caller]
     fn expect_cache(&self, key: Key) -> &Cache {
-        self.caches.get(&key).expect("Expected cache!")
+        self.caches
+            .get(&key)
+            .or_else(|| self.gone.get(&key))
+            .expect("Expected cache!")
     }
-    fn cache(&self, key: Key) -> Option<&Cache> {
+    pub(super) fn cache(&self, key: Key) -> Option<&Cache> {
         self.caches.get(&key)
     }
     fn cache_mut(&mut self, key: Key) -> Option<&mut Cache> {
         self.caches.get_mut(&key)
     }
-    #[track_caller]
-    pub(super) fn expect_cache_by_wid(&mut self, wid: WindowId) -> (&Key, &mut Cache) {
-        self.caches
-            .iter_mut()
-            .find(|(_, cache)| cache.wid == wid)
-            .expect("Expected cache!")
+    /// Only one window has input focus, others may get their `Focused(false)` after this one.
+    pub(super) fn set_focused_window(&mut self, wid: WindowId) {
+        for cache in self.caches.values_mut() {
+            cache.focus = cache.wid == wid;
+        }
     }
     pub(super) fn cache_by_wid(&mut self, wid: WindowId) -> Option<(&Key, &mut Cache)> {
         self.caches.iter_mut().find(|(_, cache)| cache.wid == wid)
@@ -221,8 +626,9 @@ impl super::callbacks::Callbacks for Proxy {
         });
     }
     fn set_position(&mut self, key: Key, pos: ImVec2) {
+        // imgui can query position before the command is applied, report the requested one
         if let Some(cache) = self.cache_mut(key) {
-            cache.pos = None;
+            cache.pos = Some(pos);
         }
         self.commands.push(Command {
             key,
@@ -231,7 +637,7 @@ impl super::callbacks::Callbacks for Proxy {
     }
     fn set_size(&mut self, key: Key, size: ImVec2) {
         if let Some(cache) = self.cache_mut(key) {
-            cache.size = None;
+            cache.size = Some(size);
         }
         self.commands.push(Command {
             key,
@@ -245,10 +651,15 @@ impl super::callbacks::Callbacks for Proxy {
         });
     }
     fn get_position(&self, key: Key) -> ImVec2 {
-        self.expect_cache(key).pos.expect("Expect cached position")
+        // unknown before the first update, or when platform can't report it
+        self.expect_cache(key)
+            .pos
+            .unwrap_or(ImVec2 { x: 0.0, y: 0.0 })
     }
     fn get_size(&self, key: Key) -> ImVec2 {
-        self.expect_cache(key).size.expect("Expect cached size")
+        self.expect_cache(key)
+            .size
+            .unwrap_or(ImVec2 { x: 0.0, y: 0.0 })
     }
     fn get_focus(&self, key: Key) -> bool {
         // cache can be none for the first frame
@@ -263,4 +674,283 @@ impl super::callbacks::Callbacks for Proxy {
             kind: Kind::SetTitle(title),
         });
     }
+    fn set_alpha(&mut self, key: Key, alpha: f32) {
+        self.commands.push(Command {
+            key,
+            kind: Kind::SetAlpha(alpha),
+        });
+    }
+    fn set_ime_pos(&mut self, key: Key, pos: ImVec2) {
+        self.commands.push(Command {
+            key,
+            kind: Kind::SetImePos(pos),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::callbacks::Callbacks;
+
+    fn dummy_wid() -> WindowId {
+        unsafe { WindowId::dummy() }
+    }
+
+    #[test]
+    fn key_and_window_round_trip() {
+        let mut proxy = Proxy::new();
+        let wid = dummy_wid();
+        let key = proxy.use_window(wid);
+        assert!(!key.is_unset());
+        assert_eq!(proxy.window_id(key), Some(wid));
+        assert_eq!(proxy.key_for_window(wid), Some(key));
+        assert_eq!(Key::from_ptr(key.into_ptr()), key);
+    }
+
+    #[test]
+    fn unknown_keys_have_no_window() {
+        let mut proxy = Proxy::new();
+        let key = proxy.use_window(dummy_wid());
+        assert_eq!(proxy.window_id(Key::UNSET), None);
+        assert_eq!(proxy.window_id(Key(key.0 + 1)), None);
+        // window of queued viewport isn't spawned yet
+        let pending = proxy.create_window(ViewportFlags::empty());
+        assert_ne!(pending, key);
+        assert_eq!(proxy.window_id(pending), None);
+    }
+
+    #[test]
+    fn unknown_geometry_reads_as_zero() {
+        let mut proxy = Proxy::new();
+        let key = proxy.use_window(dummy_wid());
+        // nothing cached before the first update
+        let pos = proxy.get_position(key);
+        let size = proxy.get_size(key);
+        assert_eq!((pos.x, pos.y, size.x, size.y), (0.0, 0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn echo_stamp_survives_intermediate_events() {
+        let mut cache = Cache::new(dummy_wid());
+        let target = PhysicalPosition::new(300, 200);
+        cache.expected_pos = Some(target);
+        // platform reports the move in steps
+        assert!(cache.is_pos_echo(PhysicalPosition::new(150, 100)));
+        assert!(cache.is_pos_echo(target));
+        // stamp is consumed, the next move is user's
+        assert!(!cache.is_pos_echo(PhysicalPosition::new(310, 200)));
+
+        let target = PhysicalSize::new(640, 480);
+        cache.expected_size = Some(target);
+        assert!(cache.is_size_echo(PhysicalSize::new(320, 240)));
+        assert!(cache.is_size_echo(target));
+        assert!(!cache.is_size_echo(PhysicalSize::new(650, 480)));
+    }
+
+    #[test]
+    fn unanswered_echo_stamp_expires() {
+        let mut cache = Cache::new(dummy_wid());
+        cache.expected_pos = Some(PhysicalPosition::new(300, 200));
+        cache.expected_size = Some(PhysicalSize::new(640, 480));
+        cache.expire_echo();
+        assert!(!cache.is_pos_echo(PhysicalPosition::new(290, 200)));
+        assert!(!cache.is_size_echo(PhysicalSize::new(630, 480)));
+    }
+
+    fn command(key: usize, kind: Kind) -> Command {
+        Command {
+            key: Key(key),
+            kind,
+        }
+    }
+
+    fn create(key: usize) -> Command {
+        command(
+            key,
+            Kind::CreateWindow {
+                flags: ViewportFlags::empty(),
+            },
+        )
+    }
+
+    fn names(commands: &[Command]) -> Vec<(usize, &'static str)> {
+        commands
+            .iter()
+            .map(|command| {
+                let name = match command.kind {
+                    Kind::CreateWindow { .. } => "create",
+                    Kind::DestroyWindow => "destroy",
+                    Kind::ShowWindow => "show",
+                    Kind::SetPos(_) => "pos",
+                    Kind::SetSize(_) => "size",
+                    _ => "other",
+                };
+                (command.key.0, name)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn coalesce_drops_window_created_and_destroyed_in_batch() {
+        let commands = vec![
+            create(1),
+            command(1, Kind::SetPos(ImVec2 { x: 10.0, y: 20.0 })),
+            command(1, Kind::ShowWindow),
+            command(1, Kind::DestroyWindow),
+            command(2, Kind::SetFocus),
+        ];
+        assert_eq!(names(&coalesce(commands)), vec![(2, "other")]);
+    }
+
+    #[test]
+    fn coalesce_keeps_only_destroy_of_existing_window() {
+        let commands = vec![
+            command(1, Kind::SetPos(ImVec2 { x: 10.0, y: 20.0 })),
+            command(1, Kind::SetTitle("title".into())),
+            command(2, Kind::SetSize(ImVec2 { x: 100.0, y: 50.0 })),
+            command(1, Kind::DestroyWindow),
+        ];
+        assert_eq!(
+            names(&coalesce(commands)),
+            vec![(2, "size"), (1, "destroy")]
+        );
+    }
+
+    #[test]
+    fn show_moves_after_placement() {
+        let commands = vec![
+            create(1),
+            command(1, Kind::ShowWindow),
+            command(1, Kind::SetSize(ImVec2 { x: 100.0, y: 50.0 })),
+            command(1, Kind::SetPos(ImVec2 { x: 10.0, y: 20.0 })),
+        ];
+        assert_eq!(
+            names(&place_before_show(commands)),
+            vec![(1, "create"), (1, "size"), (1, "pos"), (1, "show")]
+        );
+    }
+
+    #[test]
+    fn show_without_placement_keeps_order() {
+        let commands = vec![create(1), command(1, Kind::ShowWindow)];
+        assert_eq!(
+            names(&place_before_show(commands)),
+            vec![(1, "create"), (1, "show")]
+        );
+    }
+
+    #[test]
+    fn show_of_existing_window_keeps_order() {
+        let commands = vec![
+            command(1, Kind::ShowWindow),
+            command(1, Kind::SetPos(ImVec2 { x: 10.0, y: 20.0 })),
+            create(2),
+            command(2, Kind::SetPos(ImVec2 { x: 30.0, y: 40.0 })),
+            command(2, Kind::ShowWindow),
+        ];
+        assert_eq!(
+            names(&place_before_show(commands)),
+            vec![
+                (1, "show"),
+                (1, "pos"),
+                (2, "create"),
+                (2, "pos"),
+                (2, "show")
+            ]
+        );
+    }
+
+    struct NoRenderer;
+
+    #[derive(Default)]
+    struct Offscreen {
+        resized: usize,
+    }
+    impl crate::Viewport for Offscreen {
+        type Renderer = NoRenderer;
+        fn window(&self) -> Option<&winit::window::Window> {
+            None
+        }
+        fn on_resize(&mut self) {
+            self.resized += 1;
+        }
+        fn on_draw(&mut self, _renderer: &mut NoRenderer, _draw_data: &imgui::DrawData) {}
+    }
+
+    #[derive(Default)]
+    struct Headless {
+        viewports: HashMap<WindowId, Offscreen>,
+    }
+    impl Manager for Headless {
+        type Renderer = NoRenderer;
+        type Viewport = Offscreen;
+        fn viewport(&self, wid: WindowId) -> Option<&Offscreen> {
+            self.viewports.get(&wid)
+        }
+        fn viewport_mut(&mut self, wid: WindowId) -> Option<&mut Offscreen> {
+            self.viewports.get_mut(&wid)
+        }
+        fn viewports(&self) -> Box<dyn Iterator<Item = (&WindowId, &Offscreen)> + '_> {
+            Box::new(self.viewports.iter())
+        }
+        fn add_window(
+            &mut self,
+            _window: winit::window::Window,
+        ) -> Result<WindowId, crate::ManagerError> {
+            unreachable!("headless manager has no windows")
+        }
+        fn destroy(&mut self, wid: WindowId) -> bool {
+            self.viewports.remove(&wid).is_some()
+        }
+    }
+
+    #[derive(Default)]
+    struct CountShows {
+        shown: usize,
+    }
+    impl WindowSpawner<Offscreen> for CountShows {
+        fn build_window<T: 'static>(
+            &mut self,
+            _event_loop: &winit::event_loop::EventLoopWindowTarget<T>,
+            _flags: ViewportFlags,
+            _hook: Option<&mut crate::WindowBuilderHook>,
+        ) -> winit::window::Window {
+            unreachable!("headless spawner has no event loop")
+        }
+        fn show_window(&mut self, _viewport: &Offscreen) {
+            self.shown += 1;
+        }
+    }
+
+    #[test]
+    fn commands_reach_windowless_viewport() {
+        let wid = dummy_wid();
+        let mut manager = Headless::default();
+        manager.viewports.insert(wid, Offscreen::default());
+        let mut proxy = Proxy::new();
+        let key = proxy.use_window(wid);
+
+        proxy.set_size(key, ImVec2 { x: 320.0, y: 240.0 });
+        proxy.set_position(key, ImVec2 { x: 10.0, y: 20.0 });
+        proxy.show_window(key);
+        proxy.set_title(key, "offscreen".into());
+        let mut with_loop = WithLoop::headless(&mut manager, CountShows::default());
+        proxy.update(&mut with_loop).unwrap();
+        assert_eq!(with_loop.spawner.shown, 1);
+        drop(with_loop);
+
+        assert_eq!(manager.viewports[&wid].resized, 1);
+        let size = proxy.get_size(key);
+        assert_eq!((size.x, size.y), (320.0, 240.0));
+        let pos = proxy.get_position(key);
+        assert_eq!((pos.x, pos.y), (10.0, 20.0));
+
+        proxy.destroy_window(key);
+        let mut with_loop = WithLoop::headless(&mut manager, CountShows::default());
+        proxy.update(&mut with_loop).unwrap();
+        drop(with_loop);
+        assert!(manager.viewports.is_empty());
+        assert_eq!(proxy.window_id(key), None);
+    }
 }
@@ -1,13 +1,26 @@
-use crate::{Manager, Viewport};
+use crate::{FontSetup, Manager, ManagerError, Viewport, ViewportEvent, WindowBuilderHook};
 use imgui::TextureId;
 use imgui_wgpu::{RendererConfig, TextureConfig};
-use std::collections::HashMap;
-use winit::window::{Window, WindowId};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use winit::{
+    event::{Event, WindowEvent},
+    window::{Icon, Window, WindowId},
+};
 
 pub struct Wgpu {
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     renderer: imgui_wgpu::Renderer,
+    sample_count: u32,
+    texture_format: wgpu::TextureFormat,
+    depth_format: Option<wgpu::TextureFormat>,
+    /// Ids inserted by `upload_image` and `register_texture`.
+    textures: HashSet<TextureId>,
 }
 
 pub struct ImageData {
@@ -25,39 +38,268 @@ impl ImageData {
             format,
         }
     }
+    /// Copies rows padded to `stride` bytes into tightly packed image.
+    pub fn from_raw_with_stride(
+        width: u32,
+        height: u32,
+        stride: usize,
+        bytes: &[u8],
+        format: wgpu::TextureFormat,
+    ) -> Result<Self, ImageDataError> {
+        let pixel_size =
+            bytes_per_pixel(format).ok_or(ImageDataError::UnsupportedFormat(format))?;
+        let row_size = width as usize * pixel_size;
+        if stride < row_size {
+            return Err(ImageDataError::StrideTooSmall { stride, row_size });
+        }
+        if row_size == 0 {
+            // zero width, there are no rows to split `bytes` into
+            return Ok(Self::new(width, height, Vec::new(), format));
+        }
+        let expected = match height as usize {
+            0 => 0,
+            rows => stride * (rows - 1) + row_size,
+        };
+        if bytes.len() < expected {
+            return Err(ImageDataError::NotEnoughBytes {
+                len: bytes.len(),
+                expected,
+            });
+        }
+        let mut packed = Vec::with_capacity(row_size * height as usize);
+        for row in bytes.chunks(stride).take(height as usize) {
+            packed.extend_from_slice(&row[..row_size]);
+        }
+        Ok(Self::new(width, height, packed, format))
+    }
+    /// Window icon for `WgpuManager::set_window_icon`, only 8-bit RGBA and BGRA images are supported.
+    pub fn to_icon(&self) -> Result<Icon, ImageDataError> {
+        use wgpu::TextureFormat;
+        let expected = self.width as usize * self.height as usize * 4;
+        if self.bytes.len() < expected {
+            return Err(ImageDataError::NotEnoughBytes {
+                len: self.bytes.len(),
+                expected,
+            });
+        }
+        let mut rgba = self.bytes[..expected].to_vec();
+        match self.format {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => {}
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => {
+                for pixel in rgba.chunks_exact_mut(4) {
+                    pixel.swap(0, 2);
+                }
+            }
+            format => return Err(ImageDataError::UnsupportedFormat(format)),
+        }
+        Ok(Icon::from_rgba(rgba, self.width, self.height).expect("Icon size is checked"))
+    }
+    /// Converts image into `format`, sRGB formats expect image to be in sRGB color space already.
     #[cfg(feature = "from-image")]
-    pub fn from_image(image: image::DynamicImage) -> Self {
+    pub fn from_image(
+        image: image::DynamicImage,
+        format: wgpu::TextureFormat,
+    ) -> Result<Self, ImageDataError> {
         use image::GenericImageView;
         use wgpu::TextureFormat;
         let (width, height) = image.dimensions();
-        let format = Outlet::format();
         let bytes = match format {
-            TextureFormat::Bgra8Unorm => image.to_bgra().into_raw(),
-            TextureFormat::Rgba8Unorm => image.to_rgba().into_raw(),
-            _ => unimplemented!(),
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => image.to_bgra().into_raw(),
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => image.to_rgba().into_raw(),
+            _ => return Err(ImageDataError::UnsupportedFormat(format)),
         };
-        Self {
+        Ok(Self {
             width,
             height,
             bytes,
             format,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageDataError {
+    UnsupportedFormat(wgpu::TextureFormat),
+    StrideTooSmall { stride: usize, row_size: usize },
+    NotEnoughBytes { len: usize, expected: usize },
+}
+impl fmt::Display for ImageDataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageDataError::UnsupportedFormat(format) => {
+                write!(f, "unsupported texture format {:?}", format)
+            }
+            ImageDataError::StrideTooSmall { stride, row_size } => write!(
+                f,
+                "stride {} is less than row size {} bytes",
+                stride, row_size
+            ),
+            ImageDataError::NotEnoughBytes { len, expected } => {
+                write!(f, "expected at least {} bytes, got {}", expected, len)
+            }
         }
     }
 }
+impl std::error::Error for ImageDataError {}
+
+#[derive(Debug)]
+pub enum InitError {
+    /// No adapter can render to given surface with requested power preference,
+    /// or chosen adapter is gone.
+    NoAdapter,
+    RequestDevice(wgpu::RequestDeviceError),
+}
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InitError::NoAdapter => write!(f, "no compatible adapter found"),
+            InitError::RequestDevice(err) => write!(f, "failed to request device: {}", err),
+        }
+    }
+}
+impl std::error::Error for InitError {}
+
+fn bytes_per_pixel(format: wgpu::TextureFormat) -> Option<usize> {
+    use wgpu::TextureFormat::*;
+    match format {
+        R8Unorm | R8Snorm | R8Uint | R8Sint => Some(1),
+        Rg8Unorm | Rg8Snorm | Rg8Uint | Rg8Sint => Some(2),
+        Rgba8Unorm | Rgba8UnormSrgb | Rgba8Snorm | Rgba8Uint | Rgba8Sint | Bgra8Unorm
+        | Bgra8UnormSrgb => Some(4),
+        _ => None,
+    }
+}
 
 impl Wgpu {
+    /// Viewports use linear `Bgra8Unorm` swap chains and imgui shaders do gamma encoding themselves
+    /// (`RendererConfig::new_srgb`), so colors are converted exactly once.
     pub fn new(imgui: &mut imgui::Context, device: wgpu::Device, queue: wgpu::Queue) -> Self {
+        Self::with_sample_count(imgui, device, queue, 1)
+    }
+    /// Renders imgui with MSAA, only 1 and 4 samples are guaranteed to work, anything else falls back to 1.
+    pub fn with_sample_count(
+        imgui: &mut imgui::Context,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        sample_count: u32,
+    ) -> Self {
         let config = RendererConfig {
             texture_format: Outlet::format(),
+            sample_count,
             ..RendererConfig::new_srgb()
         };
+        Self::with_config(imgui, device, queue, config)
+    }
+    /// Picks adapter compatible with `surface`, creates device and renderer, blocks until done.
+    ///
+    /// Adapter is returned for logging (`get_info`) and creating more devices.
+    pub fn init(
+        imgui: &mut imgui::Context,
+        instance: &wgpu::Instance,
+        surface: Option<&wgpu::Surface>,
+        power_preference: wgpu::PowerPreference,
+        features: wgpu::Features,
+        limits: wgpu::Limits,
+    ) -> Result<(wgpu::Adapter, Self), InitError> {
+        let adapter =
+            futures::executor::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference,
+                compatible_surface: surface,
+            }))
+            .ok_or(InitError::NoAdapter)?;
+        let wgpu = Self::from_adapter(imgui, &adapter, features, limits)?;
+        Ok((adapter, wgpu))
+    }
+    /// Adapters of all backends, for GPU chooser on multi-GPU machines.
+    pub fn enumerate_adapters(instance: &wgpu::Instance) -> Vec<wgpu::AdapterInfo> {
+        instance
+            .enumerate_adapters(wgpu::BackendBit::all())
+            .map(|adapter| adapter.get_info())
+            .collect()
+    }
+    /// Like `init`, but uses adapter picked from `enumerate_adapters`.
+    ///
+    /// wgpu 0.6 can't check if adapter supports a surface beforehand, incompatible adapter
+    /// is reported by `request_device` or later by swap chain creation.
+    pub fn init_with_adapter(
+        imgui: &mut imgui::Context,
+        instance: &wgpu::Instance,
+        info: &wgpu::AdapterInfo,
+        features: wgpu::Features,
+        limits: wgpu::Limits,
+    ) -> Result<(wgpu::Adapter, Self), InitError> {
+        let adapter = instance
+            .enumerate_adapters(wgpu::BackendBit::all())
+            .find(|adapter| {
+                let other = adapter.get_info();
+                other.name == info.name
+                    && other.vendor == info.vendor
+                    && other.device == info.device
+                    && other.backend == info.backend
+            })
+            .ok_or(InitError::NoAdapter)?;
+        let wgpu = Self::from_adapter(imgui, &adapter, features, limits)?;
+        Ok((adapter, wgpu))
+    }
+    fn from_adapter(
+        imgui: &mut imgui::Context,
+        adapter: &wgpu::Adapter,
+        features: wgpu::Features,
+        limits: wgpu::Limits,
+    ) -> Result<Self, InitError> {
+        let (device, queue) = futures::executor::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features,
+                limits,
+                shader_validation: false,
+            },
+            None,
+        ))
+        .map_err(InitError::RequestDevice)?;
+        Ok(Self::new(imgui, device, queue))
+    }
+    /// Viewport swap chains use `config.texture_format`, pair `Bgra8UnormSrgb` with `RendererConfig::new`
+    /// and `Bgra8Unorm` with `RendererConfig::new_srgb`.
+    pub fn with_config(
+        imgui: &mut imgui::Context,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: RendererConfig,
+    ) -> Self {
+        let sample_count = match config.sample_count {
+            1 | 4 => config.sample_count,
+            _ => {
+                eprintln!("unsupported sample count {}, using 1", config.sample_count);
+                1
+            }
+        };
+        let texture_format = config.texture_format;
+        let depth_format = config.depth_format;
+        let config = RendererConfig {
+            sample_count,
+            ..config
+        };
         let renderer = imgui_wgpu::Renderer::new(imgui, &device, &queue, config);
         Self {
             device,
             queue,
             renderer,
+            sample_count,
+            texture_format,
+            depth_format,
+            textures: HashSet::new(),
         }
     }
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+    pub fn texture_format(&self) -> wgpu::TextureFormat {
+        self.texture_format
+    }
+    /// Depth format imgui pipeline was built with, viewports drawn by this renderer follow it.
+    pub fn depth_format(&self) -> Option<wgpu::TextureFormat> {
+        self.depth_format
+    }
     pub fn upload_image(&mut self, data: &ImageData, replace: Option<TextureId>) -> TextureId {
         let texture_config = TextureConfig {
             size: wgpu::Extent3d {
@@ -72,36 +314,167 @@ impl Wgpu {
         let texture = imgui_wgpu::Texture::new(&self.device, &self.renderer, texture_config);
 
         texture.write(&self.queue, &data.bytes, data.width, data.height);
-        if let Some(id) = replace {
+        let id = if let Some(id) = replace {
             self.renderer.textures.replace(id, texture);
             id
         } else {
             self.renderer.textures.insert(texture)
-        }
+        };
+        self.textures.insert(id);
+        id
+    }
+    /// Makes texture created outside of `upload_image` (e.g. render target) available to imgui `Image` widgets.
+    pub fn register_texture(&mut self, texture: imgui_wgpu::Texture) -> TextureId {
+        let id = self.renderer.textures.insert(texture);
+        self.textures.insert(id);
+        id
+    }
+    pub fn texture(&self, id: TextureId) -> Option<&imgui_wgpu::Texture> {
+        self.renderer.textures.get(id)
     }
+    /// Needed to create `imgui_wgpu::Texture` for `register_texture`.
+    pub fn renderer(&self) -> &imgui_wgpu::Renderer {
+        &self.renderer
+    }
+    /// Uploads font atlas again after fonts were added or changed, previous font texture id becomes invalid.
+    pub fn reload_font_texture(&mut self, imgui: &mut imgui::Context) -> TextureId {
+        let old = imgui.fonts().tex_id;
+        self.textures.remove(&old);
+        self.renderer.textures.remove(old);
+        self.renderer
+            .reload_font_texture(imgui, &self.device, &self.queue);
+        imgui.fonts().tex_id
+    }
+    /// Frees texture, returns `false` if there was no texture with such id.
+    pub fn remove_texture(&mut self, id: TextureId) -> bool {
+        self.textures.remove(&id);
+        self.renderer.textures.remove(id).is_some()
+    }
+    /// Number of textures uploaded with `upload_image` or registered, and not yet removed.
+    pub fn texture_count(&self) -> usize {
+        self.textures.len()
+    }
+}
+
+/// Rebuilds font atlas for new DPI scale and uploads it, old font texture id becomes invalid.
+pub fn rebuild_fonts(
+    imgui: &mut imgui::Context,
+    wgpu: &mut Wgpu,
+    setup: &FontSetup,
+    scale: f64,
+) -> TextureId {
+    setup.build(imgui, scale);
+    wgpu.reload_font_texture(imgui)
 }
 
 #[derive(Debug)]
 pub struct Outlet {
-    surface: wgpu::Surface,
+    /// `None` for offscreen viewports, which render into `capture` texture only.
+    surface: Option<wgpu::Surface>,
     sc_desc: wgpu::SwapChainDescriptor,
     swap_chain: Option<wgpu::SwapChain>,
+    msaa: Option<wgpu::TextureView>,
+    depth: Option<wgpu::TextureView>,
+    capture: Option<Capture>,
+}
+
+/// Copy of the frame for readback, swap chain textures can't be copied from directly.
+#[derive(Debug)]
+struct Capture {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    ready: bool,
+}
+impl Capture {
+    fn new(device: &wgpu::Device, width: u32, height: u32, format: wgpu::TextureFormat) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("viewport capture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // copy_texture_to_buffer requires rows aligned to 256 bytes
+        let bytes_per_row = width * bytes_per_pixel(format).unwrap_or(4) as u32;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = (bytes_per_row + align - 1) / align * align;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("viewport capture"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            texture,
+            view,
+            buffer,
+            width,
+            height,
+            padded_bytes_per_row,
+            ready: false,
+        }
+    }
+    fn copy(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.copy_texture_to_buffer(
+            wgpu::TextureCopyView {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::BufferCopyView {
+                buffer: &self.buffer,
+                layout: wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: self.padded_bytes_per_row,
+                    rows_per_image: self.height,
+                },
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth: 1,
+            },
+        );
+        self.ready = true;
+    }
 }
+
 impl Outlet {
-    fn new(surface: wgpu::Surface) -> Self {
+    fn new(
+        surface: Option<wgpu::Surface>,
+        present_mode: wgpu::PresentMode,
+        usage: wgpu::TextureUsage,
+    ) -> Self {
         Outlet {
             surface,
-            sc_desc: Self::desc(),
+            sc_desc: Self::desc(present_mode, usage),
             swap_chain: None,
+            msaa: None,
+            depth: None,
+            capture: None,
         }
     }
-    fn desc() -> wgpu::SwapChainDescriptor {
+    fn desc(
+        present_mode: wgpu::PresentMode,
+        usage: wgpu::TextureUsage,
+    ) -> wgpu::SwapChainDescriptor {
         wgpu::SwapChainDescriptor {
-            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+            usage,
             format: Self::format(),
             width: 0,
             height: 0,
-            present_mode: wgpu::PresentMode::Fifo,
+            present_mode,
         }
     }
     fn format() -> wgpu::TextureFormat {
@@ -109,9 +482,45 @@ impl Outlet {
     }
 }
 
+const DEFAULT_CLEAR_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.1,
+    g: 0.2,
+    b: 0.3,
+    a: 1.0,
+};
+
+/// Swap chain defaults for every viewport added to manager.
+///
+/// Format, sample count and depth format aren't here, they are dictated by `Wgpu` renderer pipelines.
+#[derive(Debug, Clone)]
+pub struct WgpuManagerConfig {
+    pub present_mode: wgpu::PresentMode,
+    /// `OUTPUT_ATTACHMENT` is always added.
+    pub usage: wgpu::TextureUsage,
+    pub clear_color: wgpu::Color,
+}
+impl Default for WgpuManagerConfig {
+    fn default() -> Self {
+        Self {
+            present_mode: wgpu::PresentMode::Fifo,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+            clear_color: DEFAULT_CLEAR_COLOR,
+        }
+    }
+}
+
 pub struct WgpuManager {
     viewports: HashMap<WindowId, WgpuViewport>,
-    instance: wgpu::Instance,
+    instance: Arc<wgpu::Instance>,
+    adapter: Option<wgpu::Adapter>,
+    config: WgpuManagerConfig,
+    main_window: Option<WindowId>,
+    redraw_on_demand: bool,
+    /// Overrides `config.present_mode` while set, see `set_low_latency`.
+    low_latency: bool,
+    window_builder_hook: Option<WindowBuilderHook>,
+    window_icon: Option<Icon>,
+    monitor_limit: Option<usize>,
 }
 
 impl Manager for WgpuManager {
@@ -124,116 +533,1447 @@ impl Manager for WgpuManager {
     fn viewport_mut(&mut self, wid: WindowId) -> Option<&mut Self::Viewport> {
         self.viewports.get_mut(&wid)
     }
-    fn add_window(&mut self, window: Window) -> WindowId {
+    fn viewports(&self) -> Box<dyn Iterator<Item = (&WindowId, &Self::Viewport)> + '_> {
+        Box::new(self.viewports_iter())
+    }
+    fn add_window(&mut self, window: Window) -> Result<WindowId, ManagerError> {
         let wid = window.id();
-        let surface = unsafe { self.instance.create_surface(&window) };
-        let viewport = WgpuViewport::with_surface(window, surface);
-        if self.viewports.insert(wid, viewport).is_some() {
-            panic!("Trying to add window with same WindowId twice");
+        if self.viewports.contains_key(&wid) {
+            return Err(ManagerError::DuplicateWindow(wid));
+        }
+        if let Some(icon) = &self.window_icon {
+            window.set_window_icon(Some(icon.clone()));
         }
-        wid
+        let surface = unsafe { self.instance.create_surface(&window) };
+        let mut viewport = WgpuViewport::with_surface(window, surface);
+        viewport.set_clear_color(self.config.clear_color);
+        viewport.set_present_mode(self.present_mode_for(&viewport));
+        viewport.set_usage(self.config.usage);
+        viewport.redraw_on_demand = self.redraw_on_demand;
+        self.viewports.insert(wid, viewport);
+        // first window is the one imgui treats as main viewport
+        self.main_window.get_or_insert(wid);
+        Ok(wid)
+    }
+    fn main_window(&self) -> Option<WindowId> {
+        self.main_window
     }
-    #[track_caller]
-    fn destroy(&mut self, wid: WindowId) {
-        let _ = self.viewports.remove(&wid).expect("No window to destroy");
+    fn window_builder_hook(&mut self) -> Option<&mut WindowBuilderHook> {
+        self.window_builder_hook.as_mut()
+    }
+    fn monitor_limit(&self) -> Option<usize> {
+        self.monitor_limit
+    }
+    /// Main window is never destroyed, it belongs to the user.
+    fn destroy(&mut self, wid: WindowId) -> bool {
+        if self.main_window == Some(wid) {
+            return false;
+        }
+        // window may be already gone, e.g. closed by user before imgui noticed
+        self.viewports.remove(&wid).is_some()
     }
 }
 
 impl WgpuManager {
     pub fn new(instance: wgpu::Instance) -> Self {
+        Self::new_with_config(instance, WgpuManagerConfig::default())
+    }
+    pub fn new_with_config(instance: wgpu::Instance, config: WgpuManagerConfig) -> Self {
+        Self::with_shared_instance(Arc::new(instance), config)
+    }
+    /// Manager that creates surfaces with the same instance as other managers.
+    ///
+    /// Managers don't own GPU device, single `Wgpu` can draw viewports of all of them,
+    /// as long as its adapter came from this instance. Device, queue and instance are `Send + Sync`,
+    /// but imgui context is not, so drawing still happens on the thread owning imgui.
+    pub fn with_shared_instance(instance: Arc<wgpu::Instance>, config: WgpuManagerConfig) -> Self {
         let viewports = HashMap::new();
         Self {
             viewports,
             instance,
+            adapter: None,
+            config,
+            main_window: None,
+            redraw_on_demand: false,
+            low_latency: false,
+            window_builder_hook: None,
+            window_icon: None,
+            monitor_limit: Some(crate::DEFAULT_MONITOR_LIMIT),
+        }
+    }
+    /// Adds viewport drawing into a texture instead of a window, see `WgpuViewport::offscreen`.
+    ///
+    /// Lets platform and draw dispatch run without display, e.g. in tests. winit can't create
+    /// window ids, offscreen viewport uses `WindowId::dummy`, so manager holds one at most.
+    pub fn add_offscreen(
+        &mut self,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+    ) -> Result<WindowId, ManagerError> {
+        let wid = unsafe { WindowId::dummy() };
+        if self.viewports.contains_key(&wid) {
+            return Err(ManagerError::DuplicateWindow(wid));
+        }
+        let mut viewport =
+            WgpuViewport::offscreen(device, width, height).ok_or(ManagerError::ZeroSize)?;
+        viewport.set_clear_color(self.config.clear_color);
+        viewport.redraw_on_demand = self.redraw_on_demand;
+        self.viewports.insert(wid, viewport);
+        self.main_window.get_or_insert(wid);
+        Ok(wid)
+    }
+    /// Creates manager together with the primary viewport, which can't be destroyed by imgui.
+    pub fn with_main_window(instance: wgpu::Instance, window: Window) -> (Self, WindowId) {
+        let mut manager = Self::new(instance);
+        let wid = manager
+            .add_window(window)
+            .expect("Empty manager can't have duplicates");
+        (manager, wid)
+    }
+    pub fn config(&self) -> &WgpuManagerConfig {
+        &self.config
+    }
+    /// Applied to windows spawned for imgui viewports, main window is created by user.
+    pub fn set_window_builder_hook(&mut self, hook: Option<WindowBuilderHook>) {
+        self.window_builder_hook = hook;
+    }
+    /// Latency profile for existing and future viewports: presents without vsync, turning it off
+    /// goes back to `config.present_mode`. Pair with `PowerPreference::HighPerformance` in `init_wgpu`.
+    ///
+    /// wgpu 0.6 can't tell which modes surface supports, so `Mailbox` is requested on Wayland,
+    /// which has no `Immediate`, and `Immediate` elsewhere. Unsupported mode still turns into `Fifo`.
+    pub fn set_low_latency(&mut self, low_latency: bool) {
+        self.low_latency = low_latency;
+        let modes: Vec<_> = self
+            .viewports
+            .iter()
+            .map(|(&wid, viewport)| (wid, self.present_mode_for(viewport)))
+            .collect();
+        for (wid, present_mode) in modes {
+            if let Some(viewport) = self.viewports.get_mut(&wid) {
+                viewport.set_present_mode(present_mode);
+            }
+        }
+    }
+    pub fn low_latency(&self) -> bool {
+        self.low_latency
+    }
+    fn present_mode_for(&self, viewport: &WgpuViewport) -> wgpu::PresentMode {
+        match &viewport.window {
+            Some(window) if self.low_latency => low_latency_mode(window),
+            _ => self.config.present_mode,
+        }
+    }
+    /// `None` reports every monitor to imgui, for video walls with more than 32 of them.
+    pub fn set_monitor_limit(&mut self, limit: Option<usize>) {
+        self.monitor_limit = limit;
+    }
+    /// Sets icon of all viewport windows, including ones spawned later.
+    pub fn set_window_icon(&mut self, icon: Icon) {
+        for viewport in self.viewports.values() {
+            if let Some(window) = &viewport.window {
+                window.set_window_icon(Some(icon.clone()));
+            }
         }
+        self.window_icon = Some(icon);
+    }
+    /// Window of the primary viewport, the first one added to manager.
+    pub fn main_window_id(&self) -> Option<WindowId> {
+        self.main_window
+    }
+    /// Clear color for viewports added after this call, existing ones are left as is.
+    pub fn set_default_clear_color(&mut self, color: wgpu::Color) {
+        self.config.clear_color = color;
     }
     pub fn instance(&self) -> &wgpu::Instance {
         &self.instance
     }
-    pub fn reqwest_redraws(&self) {
+    /// Creates `Wgpu` with adapter compatible with main window and keeps the adapter.
+    pub fn init_wgpu(
+        &mut self,
+        imgui: &mut imgui::Context,
+        power_preference: wgpu::PowerPreference,
+        features: wgpu::Features,
+        limits: wgpu::Limits,
+    ) -> Result<Wgpu, InitError> {
+        let surface = self
+            .main_window
+            .and_then(|wid| self.viewports.get(&wid))
+            .and_then(|viewport| viewport.surface());
+        let (adapter, wgpu) = Wgpu::init(
+            imgui,
+            &self.instance,
+            surface,
+            power_preference,
+            features,
+            limits,
+        )?;
+        self.adapter = Some(adapter);
+        Ok(wgpu)
+    }
+    /// Adapter used to create `Wgpu`, if it was created by `init_wgpu` or set with `set_adapter`.
+    pub fn adapter(&self) -> Option<&wgpu::Adapter> {
+        self.adapter.as_ref()
+    }
+    pub fn adapter_info(&self) -> Option<wgpu::AdapterInfo> {
+        self.adapter.as_ref().map(|adapter| adapter.get_info())
+    }
+    pub fn set_adapter(&mut self, adapter: wgpu::Adapter) {
+        self.adapter = Some(adapter);
+    }
+    /// Sum of `WgpuViewport::last_stats` of every window.
+    pub fn render_stats(&self) -> RenderStats {
+        let mut stats = RenderStats::default();
+        for viewport in self.viewports.values() {
+            stats += viewport.last_stats;
+        }
+        stats
+    }
+    /// `WgpuViewport::last_draw_duration` of every window.
+    pub fn draw_timings(&self) -> impl Iterator<Item = (WindowId, Duration)> + '_ {
+        self.viewports
+            .iter()
+            .map(|(&wid, viewport)| (wid, viewport.last_draw_duration))
+    }
+    /// For creating more managers with `with_shared_instance`.
+    pub fn shared_instance(&self) -> Arc<wgpu::Instance> {
+        Arc::clone(&self.instance)
+    }
+    /// Asks winit for `RedrawRequested` event for every viewport window.
+    pub fn request_all_redraws(&self) {
         for viewport in self.viewports.values() {
-            viewport.window().request_redraw();
+            if let Some(window) = &viewport.window {
+                window.request_redraw();
+            }
         }
     }
+    #[deprecated(note = "use `request_all_redraws`")]
+    pub fn reqwest_redraws(&self) {
+        self.request_all_redraws()
+    }
     pub fn viewports_iter(&self) -> impl Iterator<Item = (&WindowId, &WgpuViewport)> {
         self.viewports.iter()
     }
+    /// Handles lifecycle events and marks all viewports dirty on user input.
+    ///
+    /// Returns `ViewportEvent` for events of managed windows.
+    pub fn handle_event<T>(&mut self, event: &Event<T>) -> Option<ViewportEvent> {
+        self.handle_lifecycle_event(event);
+        let (window_id, event) = match event {
+            Event::WindowEvent { window_id, event } => (*window_id, event),
+            _ => return None,
+        };
+        match event {
+            // hover and focus changes can affect any viewport, not only the one under the mouse
+            WindowEvent::CursorMoved { .. }
+            | WindowEvent::CursorLeft { .. }
+            | WindowEvent::MouseInput { .. }
+            | WindowEvent::MouseWheel { .. }
+            | WindowEvent::KeyboardInput { .. }
+            | WindowEvent::ReceivedCharacter(_)
+            | WindowEvent::ModifiersChanged(_)
+            | WindowEvent::Focused(_) => self.mark_all_dirty(),
+            _ => {}
+        }
+        self.viewport_event(window_id, event)
+    }
+    /// Draw viewports only when they are dirty, instead of every `on_draw`.
+    ///
+    /// Applies to existing and future viewports.
+    pub fn set_redraw_on_demand(&mut self, on_demand: bool) {
+        self.redraw_on_demand = on_demand;
+        for viewport in self.viewports.values_mut() {
+            viewport.redraw_on_demand = on_demand;
+        }
+    }
+    /// Forces viewport to repaint on the next `on_draw`.
+    pub fn mark_dirty(&mut self, wid: WindowId) {
+        if let Some(viewport) = self.viewports.get_mut(&wid) {
+            viewport.mark_dirty();
+        }
+    }
+    pub fn mark_all_dirty(&mut self) {
+        for viewport in self.viewports.values_mut() {
+            viewport.mark_dirty();
+        }
+    }
+    /// Marks viewports whose imgui output changed since the last call, call it after `Platform::frame`
+    /// and before `request_dirty_redraws`, so changes not caused by input are drawn too.
+    ///
+    /// Hashing draw data isn't free, so it's skipped unless manager redraws on demand.
+    pub fn mark_changed_dirty(
+        &mut self,
+        platform: &crate::Platform,
+        imgui: &mut imgui::Context,
+    ) -> usize {
+        if !self.redraw_on_demand {
+            return 0;
+        }
+        let mut changed = 0;
+        platform.for_each_viewport_draw_data(imgui, self, |viewport, draw_data| {
+            if viewport.mark_dirty_if_changed(draw_data) {
+                changed += 1;
+            }
+        });
+        changed
+    }
+    /// Windows of viewports that will repaint on the next `on_draw`.
+    pub fn viewports_to_redraw(&self) -> impl Iterator<Item = WindowId> + '_ {
+        self.viewports
+            .iter()
+            .filter(|(_, viewport)| viewport.needs_redraw())
+            .map(|(&wid, _)| wid)
+    }
+    /// Like `request_all_redraws`, but skips viewports that have nothing new to draw.
+    pub fn request_dirty_redraws(&self) {
+        for viewport in self.viewports.values() {
+            if let (true, Some(window)) = (viewport.needs_redraw(), &viewport.window) {
+                window.request_redraw();
+            }
+        }
+    }
+    /// Handles `Suspended`/`Resumed` events, other events are ignored.
+    ///
+    /// Native surfaces are invalidated on suspend on some platforms (Android, Wayland),
+    /// so viewports stop drawing until resumed and get fresh surfaces after that.
+    pub fn handle_lifecycle_event<T>(&mut self, event: &Event<T>) {
+        match event {
+            Event::Suspended => self.suspend(),
+            Event::Resumed => self.resume(),
+            _ => {}
+        }
+    }
+    /// Offscreen viewports have no surface to lose and keep drawing.
+    pub fn suspend(&mut self) {
+        for viewport in self.viewports.values_mut() {
+            if viewport.window.is_none() {
+                continue;
+            }
+            viewport.drop_swap_chain();
+            viewport.suspended = true;
+        }
+    }
+    pub fn resume(&mut self) {
+        for wid in self.reconfigure_all() {
+            eprintln!("failed to recreate surface of window {:?}", wid);
+        }
+    }
+    /// Recreates surfaces and swap chains of all viewports, e.g. after GPU switch or driver reset.
+    ///
+    /// Returns windows whose surface can't be recreated, offscreen viewports have none and are skipped.
+    /// wgpu 0.6 creates surfaces infallibly, broken ones are reported by `next_frame` instead.
+    pub fn reconfigure_all(&mut self) -> Vec<WindowId> {
+        let instance = &self.instance;
+        self.viewports
+            .iter_mut()
+            .filter(|(_, viewport)| viewport.window.is_some())
+            .filter_map(|(&wid, viewport)| {
+                if viewport.recreate_surface(instance) {
+                    None
+                } else {
+                    Some(wid)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Custom rendering into viewport frame before imgui is drawn on top of it.
+///
+/// Gets the color attachment imgui renders into, which is multisampled when MSAA is enabled.
+pub type BeforeImgui = Box<dyn FnMut(&mut wgpu::CommandEncoder, &wgpu::TextureView)>;
+
+/// Frame passed to `Background`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameInfo {
+    pub width: u32,
+    pub height: u32,
+    pub scale_factor: f64,
+}
+
+/// Custom drawing inside imgui render pass, before imgui.
+///
+/// Closures can only record commands that don't bind resources (e.g. `set_viewport`),
+/// implement the trait on a type owning pipelines and buffers for anything else.
+pub trait Background {
+    fn draw<'a>(&'a mut self, rpass: &mut wgpu::RenderPass<'a>, frame: &FrameInfo);
+    /// Keeps previous frame content instead of clearing it with viewport clear color first.
+    fn load(&self) -> bool {
+        false
+    }
+}
+impl<F: FnMut(&mut wgpu::RenderPass<'_>, &FrameInfo)> Background for F {
+    fn draw<'a>(&'a mut self, rpass: &mut wgpu::RenderPass<'a>, frame: &FrameInfo) {
+        self(rpass, frame)
+    }
 }
 
 pub struct WgpuViewport {
-    window: Window,
+    window: Option<Window>,
     outlet: Outlet,
+    clear_color: wgpu::Color,
+    present_mode: wgpu::PresentMode,
+    usage: wgpu::TextureUsage,
+    depth_format: Option<wgpu::TextureFormat>,
+    sample_count: u32,
+    /// `request_capture` was called, consumed by the next draw.
+    capture_requested: bool,
+    before_imgui: Option<BeforeImgui>,
+    background: Option<Box<dyn Background>>,
+    minimized: bool,
+    focused: bool,
+    suspended: bool,
+    dirty: bool,
+    redraw_on_demand: bool,
+    clear: bool,
+    last_draw_duration: Duration,
+    last_stats: RenderStats,
+    /// `draw_data_hash` of the last draw data seen by `mark_dirty_if_changed`.
+    last_draw_hash: Option<u64>,
+    /// Last error of `next_frame`, to log only changes.
+    frame_error: Option<wgpu::SwapChainError>,
+    render_region: Option<RenderRegion>,
+}
+
+/// Geometry imgui submitted for drawing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    pub vertices: usize,
+    pub indices: usize,
+    pub draw_lists: usize,
+    /// `DrawCmd::Elements`, each is a draw call.
+    pub draw_calls: usize,
+}
+impl RenderStats {
+    pub fn from_draw_data(draw_data: &imgui::DrawData) -> Self {
+        let mut stats = Self {
+            vertices: draw_data.total_vtx_count as usize,
+            indices: draw_data.total_idx_count as usize,
+            ..Self::default()
+        };
+        for draw_list in draw_data.draw_lists() {
+            stats.draw_lists += 1;
+            stats.draw_calls += draw_list
+                .commands()
+                .filter(|cmd| matches!(cmd, imgui::DrawCmd::Elements { .. }))
+                .count();
+        }
+        stats
+    }
 }
+/// Fingerprint of geometry, clip rects and textures, equal for identical frames.
+fn draw_data_hash(draw_data: &imgui::DrawData) -> u64 {
+    use std::hash::{Hash, Hasher};
+    fn bytes<T>(slice: &[T]) -> &[u8] {
+        // vertices and indices are plain numbers without padding
+        unsafe {
+            std::slice::from_raw_parts(slice.as_ptr() as *const u8, std::mem::size_of_val(slice))
+        }
+    }
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    for value in draw_data
+        .display_pos
+        .iter()
+        .chain(&draw_data.display_size)
+        .chain(&draw_data.framebuffer_scale)
+    {
+        value.to_bits().hash(&mut hasher);
+    }
+    for draw_list in draw_data.draw_lists() {
+        hasher.write(bytes(draw_list.vtx_buffer()));
+        hasher.write(bytes(draw_list.idx_buffer()));
+        for command in draw_list.commands() {
+            match command {
+                imgui::DrawCmd::Elements { count, cmd_params } => {
+                    count.hash(&mut hasher);
+                    for value in &cmd_params.clip_rect {
+                        value.to_bits().hash(&mut hasher);
+                    }
+                    cmd_params.texture_id.id().hash(&mut hasher);
+                    cmd_params.vtx_offset.hash(&mut hasher);
+                    cmd_params.idx_offset.hash(&mut hasher);
+                }
+                imgui::DrawCmd::ResetRenderState => 0u8.hash(&mut hasher),
+                imgui::DrawCmd::RawCallback { .. } => 1u8.hash(&mut hasher),
+            }
+        }
+    }
+    hasher.finish()
+}
+impl std::ops::AddAssign for RenderStats {
+    fn add_assign(&mut self, other: Self) {
+        self.vertices += other.vertices;
+        self.indices += other.indices;
+        self.draw_lists += other.draw_lists;
+        self.draw_calls += other.draw_calls;
+    }
+}
+
+/// Part of the frame in physical pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+impl RenderRegion {
+    /// Cuts region to fit into frame of given size.
+    pub fn clamp(self, width: u32, height: u32) -> Self {
+        let x = self.x.min(width);
+        let y = self.y.min(height);
+        Self {
+            x,
+            y,
+            width: self.width.min(width - x),
+            height: self.height.min(height - y),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+}
+
+/// Copy of `draw_data` that lands in `region` when rendered into the whole frame.
+///
+/// imgui-wgpu derives both projection and scissor rects of draw commands from display rect
+/// and framebuffer scale, so shifting and scaling them moves geometry and clipping together.
+/// `None` if there is nothing to draw.
+fn draw_data_in_region(
+    draw_data: &imgui::DrawData,
+    region: RenderRegion,
+    (width, height): (u32, u32),
+) -> Option<imgui::DrawData> {
+    let [display_width, display_height] = draw_data.display_size;
+    if region.is_empty() || display_width <= 0.0 || display_height <= 0.0 {
+        return None;
+    }
+    // plain data pointing to imgui draw lists, the copy is used while the original is alive
+    let mut moved = unsafe { std::ptr::read(draw_data) };
+    let scale = [
+        region.width as f32 / display_width,
+        region.height as f32 / display_height,
+    ];
+    moved.framebuffer_scale = scale;
+    moved.display_pos = [
+        draw_data.display_pos[0] - region.x as f32 / scale[0],
+        draw_data.display_pos[1] - region.y as f32 / scale[1],
+    ];
+    moved.display_size = [width as f32 / scale[0], height as f32 / scale[1]];
+    Some(moved)
+}
+
+/// Result of `WgpuViewport::next_frame`.
+#[derive(Debug)]
+pub enum FrameOutcome {
+    Frame(wgpu::SwapChainFrame),
+    /// Nothing to draw into right now (zero sized window, timeout), try again next frame.
+    Skip,
+    /// Swap chain is outdated or lost even after rebuild, it's dropped and will be recreated next frame.
+    Recreate,
+    Fatal(wgpu::SwapChainError),
+}
+
+/// Acquired swap chain frame, commands recorded into its encoder are submitted and frame is presented on drop.
+pub struct FrameGuard<'a> {
+    queue: &'a wgpu::Queue,
+    encoder: Option<wgpu::CommandEncoder>,
+    frame: wgpu::SwapChainFrame,
+}
+impl<'a> FrameGuard<'a> {
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.frame.output.view
+    }
+    pub fn encoder(&mut self) -> &mut wgpu::CommandEncoder {
+        self.encoder.as_mut().unwrap()
+    }
+    pub fn encoder_and_view(&mut self) -> (&mut wgpu::CommandEncoder, &wgpu::TextureView) {
+        (self.encoder.as_mut().unwrap(), &self.frame.output.view)
+    }
+}
+impl<'a> Drop for FrameGuard<'a> {
+    fn drop(&mut self) {
+        if let Some(encoder) = self.encoder.take() {
+            self.queue.submit(Some(encoder.finish()));
+        }
+        // frame is presented when dropped right after this
+    }
+}
+
 impl WgpuViewport {
     fn with_surface(window: Window, surface: wgpu::Surface) -> Self {
+        Self::with_outlet(Some(window), Some(surface))
+    }
+    /// Viewport without window, imgui is drawn into owned texture which can be read back with `capture`.
+    ///
+    /// `Viewport::window` returns `None`, platform requests only update its cached geometry.
+    /// `None` if `width` or `height` is zero, textures can't be empty.
+    pub fn offscreen(device: &wgpu::Device, width: u32, height: u32) -> Option<Self> {
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let mut viewport = Self::with_outlet(None, None);
+        viewport.outlet.sc_desc.width = width;
+        viewport.outlet.sc_desc.height = height;
+        viewport.create_swap_chain(device);
+        Some(viewport)
+    }
+    fn with_outlet(window: Option<Window>, surface: Option<wgpu::Surface>) -> Self {
+        let present_mode = wgpu::PresentMode::Fifo;
+        let usage = wgpu::TextureUsage::OUTPUT_ATTACHMENT;
         Self {
             window,
-            outlet: Outlet::new(surface),
+            outlet: Outlet::new(surface, present_mode, usage),
+            clear_color: DEFAULT_CLEAR_COLOR,
+            present_mode,
+            usage,
+            depth_format: None,
+            sample_count: 1,
+            capture_requested: false,
+            before_imgui: None,
+            background: None,
+            minimized: false,
+            focused: false,
+            suspended: false,
+            dirty: true,
+            redraw_on_demand: false,
+            clear: true,
+            last_draw_duration: Duration::default(),
+            last_stats: RenderStats::default(),
+            last_draw_hash: None,
+            frame_error: None,
+            render_region: None,
+        }
+    }
+    /// Creates new surface for current native window handle, swap chain is rebuilt on the next frame.
+    ///
+    /// Returns `false` for offscreen viewports, they have no surface.
+    pub fn recreate_surface(&mut self, instance: &wgpu::Instance) -> bool {
+        match &self.window {
+            Some(window) => {
+                self.drop_swap_chain();
+                self.outlet.surface = Some(unsafe { instance.create_surface(window) });
+                self.suspended = false;
+                true
+            }
+            None => false,
+        }
+    }
+    /// `next_frame` wrapped into guard, returns `Ok(None)` when there is nothing to draw into
+    /// this time: window has zero area (e.g. minimized), viewport is offscreen, frame timed out
+    /// or swap chain is being rebuilt.
+    pub fn acquire_frame<'a>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &'a wgpu::Queue,
+    ) -> Result<Option<FrameGuard<'a>>, wgpu::SwapChainError> {
+        let frame = match self.next_frame(device) {
+            FrameOutcome::Frame(frame) => frame,
+            FrameOutcome::Skip | FrameOutcome::Recreate => return Ok(None),
+            FrameOutcome::Fatal(error) => return Err(error),
+        };
+        let encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        Ok(Some(FrameGuard {
+            queue,
+            encoder: Some(encoder),
+            frame,
+        }))
+    }
+    /// Gets frame and classifies failures, errors are logged once when they start happening.
+    pub fn next_frame(&mut self, device: &wgpu::Device) -> FrameOutcome {
+        let result = self.get_current_frame(device);
+        let error = result.as_ref().err().copied();
+        if self.frame_error != error {
+            match error {
+                Some(error) => eprintln!("dropping frames: {:?}", error),
+                None => eprintln!("frames are back"),
+            }
+            self.frame_error = error;
+        }
+        match result {
+            Ok(Some(frame)) => FrameOutcome::Frame(frame),
+            Ok(None) | Err(wgpu::SwapChainError::Timeout) => FrameOutcome::Skip,
+            Err(wgpu::SwapChainError::Outdated) | Err(wgpu::SwapChainError::Lost) => {
+                self.drop_swap_chain();
+                FrameOutcome::Recreate
+            }
+            Err(error) => FrameOutcome::Fatal(error),
         }
     }
     fn get_current_frame(
         &mut self,
         device: &wgpu::Device,
-    ) -> Result<wgpu::SwapChainFrame, wgpu::SwapChainError> {
+    ) -> Result<Option<wgpu::SwapChainFrame>, wgpu::SwapChainError> {
+        if self.outlet.surface.is_none() {
+            return Ok(None);
+        }
+        let (width, height) = self.target_size();
+        if width == 0 || height == 0 {
+            // swap chain can't have zero size
+            self.drop_swap_chain();
+            return Ok(None);
+        }
+        // resizes are batched here, so swap chain is rebuilt at most once per frame
+        if self.outlet.sc_desc.width != width || self.outlet.sc_desc.height != height {
+            self.drop_swap_chain();
+        }
         if self.outlet.swap_chain.is_none() {
             self.create_swap_chain(device);
         }
-        self.outlet.swap_chain.as_mut().unwrap().get_current_frame()
+        match self.outlet.swap_chain.as_mut().unwrap().get_current_frame() {
+            Err(wgpu::SwapChainError::Lost) | Err(wgpu::SwapChainError::Outdated) => {
+                // surface changed under our feet (gpu reset, monitor reconfiguration), rebuild and retry once
+                self.drop_swap_chain();
+                self.create_swap_chain(device);
+                self.outlet
+                    .swap_chain
+                    .as_mut()
+                    .unwrap()
+                    .get_current_frame()
+                    .map(Some)
+            }
+            other => other.map(Some),
+        }
     }
     fn create_swap_chain(&mut self, device: &wgpu::Device) {
+        let sample_count = self.sample_count;
+        let (width, height) = self.target_size();
+        let size = winit::dpi::PhysicalSize::new(width, height);
         let outlet = &mut self.outlet;
-        let size = self.window.inner_size();
         outlet.sc_desc.width = size.width;
         outlet.sc_desc.height = size.height;
-        outlet.swap_chain = Some(device.create_swap_chain(&outlet.surface, &outlet.sc_desc));
+        // wgpu falls back to `Fifo` by itself if the surface doesn't support requested mode
+        outlet.sc_desc.present_mode = self.present_mode;
+        outlet.sc_desc.usage = self.usage;
+        outlet.swap_chain = outlet
+            .surface
+            .as_ref()
+            .map(|surface| device.create_swap_chain(surface, &outlet.sc_desc));
+        outlet.msaa = if sample_count > 1 {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("viewport msaa"),
+                size: wgpu::Extent3d {
+                    width: size.width,
+                    height: size.height,
+                    depth: 1,
+                },
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format: outlet.sc_desc.format,
+                usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+            });
+            Some(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+        } else {
+            None
+        };
+        outlet.depth = self.depth_format.map(|format| {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("viewport depth"),
+                size: wgpu::Extent3d {
+                    width: size.width,
+                    height: size.height,
+                    depth: 1,
+                },
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+            });
+            texture.create_view(&wgpu::TextureViewDescriptor::default())
+        });
+        // windowed viewports create it on `request_capture`
+        outlet.capture = if self.window.is_none() {
+            Some(Capture::new(
+                device,
+                size.width,
+                size.height,
+                outlet.sc_desc.format,
+            ))
+        } else {
+            None
+        };
+    }
+    /// Renders into capture texture and copies it for readback, there is nothing to present.
+    fn draw_offscreen(&mut self, wgpu: &mut Wgpu, draw_data: &imgui::DrawData) {
+        if self.outlet.capture.is_none() {
+            self.create_swap_chain(&wgpu.device);
+        }
+        let mut encoder = wgpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.record_capture(
+            &mut wgpu.renderer,
+            &wgpu.device,
+            &wgpu.queue,
+            &mut encoder,
+            draw_data,
+        );
+        wgpu.queue.submit(Some(encoder.finish()));
+        self.capture_requested = false;
+        self.dirty = false;
+    }
+    /// Records the pass drawing `before_imgui`, background and imgui into `view`.
+    fn record_pass(
+        &mut self,
+        renderer: &mut imgui_wgpu::Renderer,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        draw_data: &imgui::DrawData,
+    ) {
+        let scale_factor = self.scale_factor();
+        let (width, height) = (self.outlet.sc_desc.width, self.outlet.sc_desc.height);
+        let (attachment, resolve_target) = match &self.outlet.msaa {
+            Some(msaa) => (msaa, Some(view)),
+            None => (view, None),
+        };
+        let background_load = self
+            .background
+            .as_ref()
+            .map_or(false, |background| background.load());
+        let load = match &mut self.before_imgui {
+            Some(before_imgui) => {
+                before_imgui(encoder, attachment);
+                wgpu::LoadOp::Load
+            }
+            // the rest of the frame belongs to user
+            None if self.render_region.is_some() && self.outlet.msaa.is_none() => {
+                wgpu::LoadOp::Load
+            }
+            None if background_load => wgpu::LoadOp::Load,
+            None if !self.clear && self.redraw_on_demand => wgpu::LoadOp::Load,
+            None => wgpu::LoadOp::Clear(self.clear_color),
+        };
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment,
+                resolve_target,
+                ops: wgpu::Operations { load, store: true },
+            }],
+            depth_stencil_attachment: depth_attachment(
+                self.outlet.depth.as_ref(),
+                self.depth_format,
+            ),
+        });
+
+        if let Some(background) = &mut self.background {
+            let frame = FrameInfo {
+                width,
+                height,
+                scale_factor,
+            };
+            background.draw(&mut rpass, &frame);
+        }
+
+        match self.render_region.map(|region| region.clamp(width, height)) {
+            Some(region) if region.is_empty() => {}
+            Some(region) => {
+                if let Some(draw_data) = draw_data_in_region(draw_data, region, (width, height)) {
+                    renderer
+                        .render(&draw_data, queue, device, &mut rpass)
+                        .expect("Rendering failed");
+                }
+            }
+            None => renderer
+                .render(draw_data, queue, device, &mut rpass)
+                .expect("Rendering failed"),
+        }
+    }
+    /// Records the same pass into capture texture and copies it for readback.
+    fn record_capture(
+        &mut self,
+        renderer: &mut imgui_wgpu::Renderer,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        draw_data: &imgui::DrawData,
+    ) {
+        let (width, height) = (self.outlet.sc_desc.width, self.outlet.sc_desc.height);
+        let format = self.outlet.sc_desc.format;
+        let mut capture = self
+            .outlet
+            .capture
+            .take()
+            .unwrap_or_else(|| Capture::new(device, width, height, format));
+        self.record_pass(renderer, device, queue, encoder, &capture.view, draw_data);
+        capture.copy(encoder);
+        self.outlet.capture = Some(capture);
+    }
+    /// `None` for offscreen viewports.
+    pub fn surface(&self) -> Option<&wgpu::Surface> {
+        self.outlet.surface.as_ref()
+    }
+    /// Window size, or fixed texture size for offscreen viewports.
+    fn target_size(&self) -> (u32, u32) {
+        match &self.window {
+            Some(window) => {
+                let size = window.inner_size();
+                (size.width, size.height)
+            }
+            None => (self.outlet.sc_desc.width, self.outlet.sc_desc.height),
+        }
+    }
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+    /// Marks viewport dirty if imgui produced draw data different from the last one,
+    /// e.g. animation, blinking text cursor or popup opened from code. Returns `true` if it changed.
+    pub fn mark_dirty_if_changed(&mut self, draw_data: &imgui::DrawData) -> bool {
+        let hash = draw_data_hash(draw_data);
+        if self.last_draw_hash == Some(hash) {
+            return false;
+        }
+        self.last_draw_hash = Some(hash);
+        self.dirty = true;
+        true
+    }
+    /// Always `true` unless manager redraws on demand.
+    pub fn needs_redraw(&self) -> bool {
+        self.dirty || !self.redraw_on_demand
     }
-    pub fn surface(&self) -> &wgpu::Surface {
-        &self.outlet.surface
+    fn drop_swap_chain(&mut self) {
+        // nothing to present until the next draw
+        self.dirty = true;
+        self.outlet.swap_chain = None;
+        self.outlet.msaa = None;
+        self.outlet.depth = None;
+        self.outlet.capture = None;
+    }
+    pub fn minimized(&self) -> bool {
+        self.minimized
+    }
+    pub fn focused(&self) -> bool {
+        self.focused
+    }
+    /// See `set_window_opacity`, offscreen viewports are ignored.
+    pub fn set_opacity(&mut self, opacity: f32) -> bool {
+        match &self.window {
+            Some(window) => crate::set_window_opacity(window, opacity),
+            None => false,
+        }
     }
+    /// CPU time of the last completed `on_draw`: acquiring the frame, recording and submitting.
+    ///
+    /// GPU execution isn't included: timestamp queries (`QuerySet`) don't exist in wgpu 0.6.
+    pub fn last_draw_duration(&self) -> Duration {
+        self.last_draw_duration
+    }
+    /// Geometry of the last `on_draw` that wasn't skipped.
+    pub fn last_stats(&self) -> RenderStats {
+        self.last_stats
+    }
+    pub fn set_clear_color(&mut self, color: wgpu::Color) {
+        self.clear_color = color;
+    }
+    /// With `false` frames are drawn over the previous ones, for redrawing static ui incrementally.
+    ///
+    /// Only takes effect with redraw on demand. Swap chain rotates several images and wgpu doesn't
+    /// promise they keep content after present, so loaded pixels can be from an older frame
+    /// (`Mailbox` drops frames unpredictably) or undefined on some backends.
+    pub fn set_clear(&mut self, clear: bool) {
+        self.clear = clear;
+    }
+    /// Swap chain will be recreated with new mode on the next frame.
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) {
+        if self.present_mode != present_mode {
+            self.present_mode = present_mode;
+            self.drop_swap_chain();
+        }
+    }
+    /// Swap chain format, follows `Wgpu::texture_format` of the renderer used for drawing.
+    ///
+    /// wgpu 0.6 can't report preferred surface format, pick it with `Wgpu::with_config`.
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.outlet.sc_desc.format
+    }
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        self.present_mode
+    }
+    /// Swap chain textures are always `OUTPUT_ATTACHMENT`, extra flags are added to it.
+    ///
+    /// Some backends don't allow copying from swap chain textures (e.g. `COPY_SRC` on GL and some
+    /// Vulkan drivers), wgpu reports it as validation error when swap chain is recreated.
+    /// `capture` doesn't need `COPY_SRC`, it draws into a texture of its own.
+    pub fn set_usage(&mut self, usage: wgpu::TextureUsage) {
+        let usage = usage | wgpu::TextureUsage::OUTPUT_ATTACHMENT;
+        if self.usage != usage {
+            self.usage = usage;
+            self.drop_swap_chain();
+        }
+    }
+    pub fn usage(&self) -> wgpu::TextureUsage {
+        self.usage
+    }
+    /// Makes the next drawn frame available to `capture`, offscreen viewports capture every frame.
+    ///
+    /// Swap chain textures can't be read, so that frame is drawn twice: into the swap chain
+    /// and into a separate texture, with the same `before_imgui`, background and render region.
+    pub fn request_capture(&mut self) {
+        self.capture_requested = true;
+        self.dirty = true;
+    }
+    /// Reads back the frame drawn after the last `request_capture`, until swap chain is rebuilt.
+    /// Blocks until GPU is done.
+    pub fn capture(&mut self, wgpu: &Wgpu) -> Option<ImageData> {
+        let capture = self
+            .outlet
+            .capture
+            .as_ref()
+            .filter(|capture| capture.ready)?;
+        let slice = capture.buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        wgpu.device.poll(wgpu::Maintain::Wait);
+        futures::executor::block_on(mapping).ok()?;
+        let image = ImageData::from_raw_with_stride(
+            capture.width,
+            capture.height,
+            capture.padded_bytes_per_row as usize,
+            &slice.get_mapped_range(),
+            self.outlet.sc_desc.format,
+        )
+        .ok();
+        capture.buffer.unmap();
+        image
+    }
+    /// imgui doesn't clear the frame while callback is set, so its content stays under the ui.
+    pub fn set_before_imgui(&mut self, before_imgui: Option<BeforeImgui>) {
+        self.before_imgui = before_imgui;
+    }
+    /// Background is drawn in the same render pass as imgui, right before it.
+    pub fn set_background(&mut self, background: Option<Box<dyn Background>>) {
+        self.background = background;
+    }
+    /// Draws imgui scaled into part of the window, the rest of the frame isn't cleared.
+    ///
+    /// Region is clamped to the frame. With MSAA imgui is drawn into multisampled texture,
+    /// which can't hold previous swap chain content, so the frame is cleared and the rest
+    /// of it has to be drawn by `before_imgui` or background.
+    pub fn set_render_region(&mut self, region: Option<RenderRegion>) {
+        self.render_region = region;
+        self.dirty = true;
+    }
+    pub fn render_region(&self) -> Option<RenderRegion> {
+        self.render_region
+    }
+    /// MSAA targets have to match sample count of imgui renderer.
+    fn use_sample_count(&mut self, sample_count: u32) {
+        if self.sample_count != sample_count {
+            self.sample_count = sample_count;
+            self.drop_swap_chain();
+        }
+    }
+    /// Swap chain format has to match color target of imgui renderer.
+    fn use_format(&mut self, format: wgpu::TextureFormat) {
+        if self.outlet.sc_desc.format != format {
+            self.outlet.sc_desc.format = format;
+            self.drop_swap_chain();
+        }
+    }
+    /// Depth buffer has to match depth format of imgui pipeline, set by `RendererConfig::depth_format`
+    /// in `Wgpu::with_config`.
+    fn use_depth_format(&mut self, depth_format: Option<wgpu::TextureFormat>) {
+        if self.depth_format != depth_format {
+            self.depth_format = depth_format;
+            self.drop_swap_chain();
+        }
+    }
+}
+
+/// Mode without vsync the window's surface most likely supports.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+fn low_latency_mode(window: &Window) -> wgpu::PresentMode {
+    use winit::platform::unix::WindowExtUnix;
+    if window.wayland_display().is_some() {
+        wgpu::PresentMode::Mailbox
+    } else {
+        wgpu::PresentMode::Immediate
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+)))]
+fn low_latency_mode(_window: &Window) -> wgpu::PresentMode {
+    wgpu::PresentMode::Immediate
+}
+
+fn depth_attachment(
+    depth: Option<&wgpu::TextureView>,
+    format: Option<wgpu::TextureFormat>,
+) -> Option<wgpu::RenderPassDepthStencilAttachmentDescriptor> {
+    let depth = depth?;
+    Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+        attachment: depth,
+        depth_ops: Some(wgpu::Operations {
+            load: wgpu::LoadOp::Clear(1.0),
+            store: true,
+        }),
+        stencil_ops: match format {
+            Some(wgpu::TextureFormat::Depth24PlusStencil8) => Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(0),
+                store: true,
+            }),
+            _ => None,
+        },
+    })
 }
 
 impl Viewport for WgpuViewport {
     type Renderer = Wgpu;
-    fn window(&self) -> &Window {
-        &self.window
+    fn window(&self) -> Option<&Window> {
+        self.window.as_ref()
     }
     fn on_resize(&mut self) {
-        self.outlet.swap_chain = None;
+        // swap chain is rebuilt in `get_current_frame`, which compares its size with the window
+        self.dirty = true;
+    }
+    fn on_scale_factor_changed(&mut self, _scale_factor: f64) {
+        // swap chain is stale already, new size comes with the following resize
+        self.drop_swap_chain();
+    }
+    fn set_minimized(&mut self, minimized: bool) {
+        if self.minimized && !minimized {
+            self.dirty = true;
+        }
+        self.minimized = minimized;
+    }
+    fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
     }
     fn on_draw(&mut self, wgpu: &mut Wgpu, draw_data: &imgui::DrawData) {
-        let mut encoder: wgpu::CommandEncoder = wgpu
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        let frame = match self.get_current_frame(&wgpu.device) {
-            Ok(frame) => frame,
-            Err(e) => {
-                eprintln!("dropped frame: {:?}", e);
-                return;
+        // suspended surface may be already destroyed by the system
+        if self.minimized || self.suspended || !self.needs_redraw() {
+            return;
+        }
+        let start = Instant::now();
+        self.last_stats = RenderStats::from_draw_data(draw_data);
+        self.use_sample_count(wgpu.sample_count);
+        self.use_format(wgpu.texture_format);
+        self.use_depth_format(wgpu.depth_format);
+        if self.window.is_none() {
+            self.draw_offscreen(wgpu, draw_data);
+            self.last_draw_duration = start.elapsed();
+            return;
+        }
+        let mut frame = match self.acquire_frame(&wgpu.device, &wgpu.queue) {
+            Ok(Some(frame)) => frame,
+            // already logged by `next_frame`
+            Ok(None) | Err(_) => return,
+        };
+        let (encoder, view) = frame.encoder_and_view();
+        self.record_pass(
+            &mut wgpu.renderer,
+            &wgpu.device,
+            &wgpu.queue,
+            encoder,
+            view,
+            draw_data,
+        );
+        if std::mem::take(&mut self.capture_requested) {
+            self.record_capture(
+                &mut wgpu.renderer,
+                &wgpu.device,
+                &wgpu.queue,
+                encoder,
+                draw_data,
+            );
+        }
+        drop(frame);
+        self.dirty = false;
+        self.last_draw_duration = start.elapsed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init_wgpu(imgui: &mut imgui::Context) -> Option<Wgpu> {
+        let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
+        match Wgpu::init(
+            imgui,
+            &instance,
+            None,
+            wgpu::PowerPreference::default(),
+            wgpu::Features::empty(),
+            wgpu::Limits::default(),
+        ) {
+            Ok((_, wgpu)) => Some(wgpu),
+            Err(err) => {
+                eprintln!("skipping, {}", err);
+                None
             }
+        }
+    }
+
+    #[test]
+    fn font_reload_replaces_atlas_texture() {
+        let _lock = crate::IMGUI_LOCK
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let mut imgui = imgui::Context::create();
+        let mut wgpu = match init_wgpu(&mut imgui) {
+            Some(wgpu) => wgpu,
+            None => return,
         };
+        let old = imgui.fonts().tex_id;
+        assert!(wgpu.texture(old).is_some());
+
+        imgui
+            .fonts()
+            .add_font(&[imgui::FontSource::DefaultFontData {
+                config: Some(imgui::FontConfig {
+                    size_pixels: 26.0,
+                    ..Default::default()
+                }),
+            }]);
+        let new = wgpu.reload_font_texture(&mut imgui);
 
-        let clear_color = wgpu::Color {
-            r: 0.1,
-            g: 0.2,
-            b: 0.3,
-            a: 1.0,
+        assert_ne!(old, new);
+        assert!(wgpu.texture(old).is_none());
+        assert!(wgpu.texture(new).is_some());
+        assert_eq!(wgpu.texture_count(), 0);
+    }
+
+    #[test]
+    fn offscreen_viewport_draws_without_window() {
+        let _lock = crate::IMGUI_LOCK
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let mut imgui = imgui::Context::create();
+        let mut wgpu = match init_wgpu(&mut imgui) {
+            Some(wgpu) => wgpu,
+            None => return,
         };
-        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                attachment: &frame.output.view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(clear_color),
-                    store: true,
-                },
-            }],
-            depth_stencil_attachment: None,
+        assert!(WgpuViewport::offscreen(&wgpu.device, 0, 32).is_none());
+        let mut viewport = WgpuViewport::offscreen(&wgpu.device, 64, 32).unwrap();
+        assert!(viewport.window().is_none());
+        assert!(viewport.raw_window_handle().is_none());
+        assert_eq!(viewport.scale_factor(), 1.0);
+
+        imgui.io_mut().display_size = [64.0, 32.0];
+        let ui = imgui.frame();
+        ui.text("offscreen");
+        viewport.on_draw(&mut wgpu, ui.render());
+
+        let image = viewport
+            .capture(&wgpu)
+            .expect("offscreen frame wasn't captured");
+        assert_eq!((image.width, image.height), (64, 32));
+        assert_eq!(image.bytes.len(), 64 * 32 * 4);
+    }
+
+    #[test]
+    fn platform_draws_offscreen_main_viewport() {
+        let _lock = crate::IMGUI_LOCK
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let mut imgui = imgui::Context::create();
+        let mut wgpu = match init_wgpu(&mut imgui) {
+            Some(wgpu) => wgpu,
+            None => return,
+        };
+        let mut manager = WgpuManager::new(wgpu::Instance::new(wgpu::BackendBit::empty()));
+        assert_eq!(
+            manager.add_offscreen(&wgpu.device, 64, 0),
+            Err(ManagerError::ZeroSize)
+        );
+        let wid = manager.add_offscreen(&wgpu.device, 64, 32).unwrap();
+        assert_eq!(manager.main_window_id(), Some(wid));
+
+        let size = winit::dpi::PhysicalSize::new(64, 32);
+        let mut platform = crate::Platform::init_offscreen(&mut imgui, wid, size);
+        let mut with_loop = crate::WithLoop::headless(&mut manager, crate::DefaultSpawner);
+        platform
+            .frame(&mut imgui, &mut with_loop, |ui, _| ui.text("offscreen"))
+            .unwrap();
+        drop(with_loop);
+        assert_eq!(platform.render_all(&mut imgui, &mut manager, &mut wgpu), 1);
+
+        let image = manager
+            .viewport_mut(wid)
+            .unwrap()
+            .capture(&wgpu)
+            .expect("offscreen frame wasn't captured");
+        assert_eq!((image.width, image.height), (64, 32));
+        crate::unregister_platform(&mut imgui);
+    }
+
+    #[test]
+    fn viewport_redraw_bookkeeping() {
+        let mut viewport = WgpuViewport::with_outlet(None, None);
+        assert!(viewport.needs_redraw());
+        // as after a completed draw
+        viewport.dirty = false;
+        assert!(
+            viewport.needs_redraw(),
+            "continuous mode redraws every time"
+        );
+
+        viewport.redraw_on_demand = true;
+        assert!(!viewport.needs_redraw());
+        viewport.mark_dirty();
+        assert!(viewport.needs_redraw());
+
+        viewport.dirty = false;
+        viewport.set_minimized(true);
+        assert!(!viewport.needs_redraw());
+        viewport.set_minimized(false);
+        assert!(viewport.needs_redraw(), "restored window is stale");
+
+        viewport.dirty = false;
+        viewport.on_resize();
+        assert!(viewport.needs_redraw());
+    }
+
+    #[test]
+    fn manager_redraws_dirty_viewports() {
+        let wid = unsafe { WindowId::dummy() };
+        let mut manager = WgpuManager::new(wgpu::Instance::new(wgpu::BackendBit::empty()));
+        manager
+            .viewports
+            .insert(wid, WgpuViewport::with_outlet(None, None));
+        manager.set_redraw_on_demand(true);
+        assert_eq!(manager.viewports_to_redraw().collect::<Vec<_>>(), vec![wid]);
+
+        manager.viewports.get_mut(&wid).unwrap().dirty = false;
+        assert_eq!(manager.viewports_to_redraw().count(), 0);
+        manager.mark_dirty(wid);
+        assert_eq!(manager.viewports_to_redraw().collect::<Vec<_>>(), vec![wid]);
+
+        manager.viewports.get_mut(&wid).unwrap().dirty = false;
+        manager.handle_event(&Event::<()>::WindowEvent {
+            window_id: wid,
+            event: WindowEvent::ReceivedCharacter('a'),
         });
+        assert_eq!(manager.viewports_to_redraw().collect::<Vec<_>>(), vec![wid]);
 
-        wgpu.renderer
-            .render(draw_data, &wgpu.queue, &wgpu.device, &mut rpass)
-            .expect("Rendering failed");
-        drop(rpass);
-        wgpu.queue.submit(Some(encoder.finish()));
+        // continuous mode again
+        manager.set_redraw_on_demand(false);
+        manager.viewports.get_mut(&wid).unwrap().dirty = false;
+        assert_eq!(manager.viewports_to_redraw().count(), 1);
+    }
+
+    #[test]
+    fn main_window_is_not_destroyed() {
+        let wid = unsafe { WindowId::dummy() };
+        let mut manager = WgpuManager::new(wgpu::Instance::new(wgpu::BackendBit::empty()));
+        manager
+            .viewports
+            .insert(wid, WgpuViewport::with_outlet(None, None));
+        manager.main_window = Some(wid);
+        assert!(!manager.destroy(wid));
+        assert!(manager.viewport(wid).is_some());
+
+        manager.main_window = None;
+        assert!(manager.destroy(wid));
+        assert!(!manager.destroy(wid));
+    }
+
+    #[test]
+    fn offscreen_viewport_survives_suspend() {
+        let wid = unsafe { WindowId::dummy() };
+        let mut manager = WgpuManager::new(wgpu::Instance::new(wgpu::BackendBit::empty()));
+        manager
+            .viewports
+            .insert(wid, WgpuViewport::with_outlet(None, None));
+        manager.handle_lifecycle_event(&Event::<()>::Suspended);
+        assert!(!manager.viewports[&wid].suspended);
+        manager.handle_lifecycle_event(&Event::<()>::Resumed);
+        assert!(!manager.viewports[&wid].suspended);
+    }
+
+    #[test]
+    fn changed_draw_data_marks_viewport_dirty() {
+        let _lock = crate::IMGUI_LOCK
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let mut imgui = imgui::Context::create();
+        imgui.fonts().build_rgba32_texture();
+        imgui.io_mut().display_size = [64.0, 64.0];
+        let mut viewport = WgpuViewport::with_outlet(None, None);
+        viewport.redraw_on_demand = true;
+        let mut draw_rect = |viewport: &mut WgpuViewport, x: f32| {
+            let ui = imgui.frame();
+            ui.get_background_draw_list()
+                .add_rect([x, 0.0], [x + 8.0, 8.0], [1.0, 1.0, 1.0])
+                .build();
+            let changed = viewport.mark_dirty_if_changed(ui.render());
+            // as after a completed draw
+            viewport.dirty = false;
+            changed
+        };
+        assert!(draw_rect(&mut viewport, 0.0));
+        assert!(!draw_rect(&mut viewport, 0.0));
+        assert!(!viewport.needs_redraw());
+        // e.g. animation without any input
+        assert!(draw_rect(&mut viewport, 4.0));
+    }
+
+    #[test]
+    fn region_draw_data_maps_display_into_region() {
+        let _lock = crate::IMGUI_LOCK
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let mut imgui = imgui::Context::create();
+        imgui.fonts().build_rgba32_texture();
+        imgui.io_mut().display_size = [100.0, 50.0];
+        let ui = imgui.frame();
+        let draw_data = ui.render();
+        let region = RenderRegion {
+            x: 20,
+            y: 10,
+            width: 50,
+            height: 25,
+        };
+        let moved = draw_data_in_region(draw_data, region, (200, 100)).unwrap();
+        // the way imgui-wgpu turns display coordinates into framebuffer pixels
+        let to_pixels = |[x, y]: [f32; 2]| {
+            [
+                (x - moved.display_pos[0]) * moved.framebuffer_scale[0],
+                (y - moved.display_pos[1]) * moved.framebuffer_scale[1],
+            ]
+        };
+        assert_eq!(to_pixels([0.0, 0.0]), [20.0, 10.0]);
+        assert_eq!(to_pixels([100.0, 50.0]), [70.0, 35.0]);
+        // whole frame is the render target
+        assert_eq!(
+            [
+                moved.display_size[0] * moved.framebuffer_scale[0],
+                moved.display_size[1] * moved.framebuffer_scale[1],
+            ],
+            [200.0, 100.0]
+        );
+
+        let empty = RenderRegion { width: 0, ..region };
+        assert!(draw_data_in_region(draw_data, empty, (200, 100)).is_none());
+    }
+
+    #[test]
+    fn zero_width_image_from_stride_is_empty() {
+        let image =
+            ImageData::from_raw_with_stride(0, 3, 0, &[], wgpu::TextureFormat::Rgba8Unorm).unwrap();
+        assert_eq!((image.width, image.height), (0, 3));
+        assert!(image.bytes.is_empty());
+
+        let image =
+            ImageData::from_raw_with_stride(1, 2, 8, &[1; 12], wgpu::TextureFormat::Rgba8Unorm)
+                .unwrap();
+        assert_eq!(image.bytes.len(), 8);
     }
 }